@@ -0,0 +1,306 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::hash::Hash;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use error::MupsError;
+use fd::FdTarget;
+use procfs::{strip_deleted, ProcFs};
+
+/// A deleted file that a process still has open
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeletedFile {
+    pub pid: u32,
+    pub fd: u32,
+    /// Where the file used to be, without the ` (deleted)` suffix
+    pub path: PathBuf,
+    pub size: u64,
+    pub dev: u64,
+    pub inode: u64,
+    /// Mount point of the filesystem, as seen by the process
+    pub mount: Option<PathBuf>,
+}
+
+/// Space held by a number of files
+///
+/// A file open in several descriptors is only counted once.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpaceUsage {
+    pub files: usize,
+    pub size: u64,
+}
+
+/// Find the deleted files that processes still hold open
+///
+/// The sizes come from following the descriptor links, which works
+/// even though the files have no name anymore. Processes that may not
+/// be looked at and descriptors closed meanwhile are skipped.
+pub fn find_deleted(procfs: &ProcFs) -> Result<Vec<DeletedFile>, MupsError> {
+    let mut deleted = Vec::new();
+
+    for pid in procfs.pids()? {
+        let fds = match procfs.fds(pid) {
+            Ok(fds) => fds,
+            Err(MupsError::NoSuchProcess(_)) | Err(MupsError::PermissionDenied(_)) => {
+                continue;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+
+        let mut mounts = None;
+
+        for fd in fds {
+            let name = format!("fd/{}", fd);
+            let path = match procfs.read_link(pid, &name) {
+                Ok(target) => match deleted_path(&target) {
+                    Some(path) => path,
+                    None => {
+                        continue;
+                    }
+                },
+                Err(_) => {
+                    continue;
+                }
+            };
+
+            let metadata = match fs::metadata(procfs.pid_path(pid, &name)) {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    continue;
+                }
+            };
+
+            /* Only read the mount table of processes that need it */
+            let mounts = mounts.get_or_insert_with(|| read_mounts(procfs, pid));
+            let mount = mounts
+                .iter()
+                .rev()
+                .find(|m| m.0 == metadata.dev())
+                .map(|m| m.1.clone());
+
+            deleted.push(DeletedFile {
+                pid,
+                fd,
+                path,
+                size: metadata.len(),
+                dev: metadata.dev(),
+                inode: metadata.ino(),
+                mount,
+            });
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// Where a deleted file used to be, from the target of a descriptor
+///
+/// Files from `memfd_create` look like deleted files named like
+/// `/memfd:name`, but they take memory instead of disk space, so they
+/// are left out.
+fn deleted_path(target: &Path) -> Option<PathBuf> {
+    let path = match FdTarget::parse(target) {
+        FdTarget::Path(path) => path,
+        _ => {
+            return None;
+        }
+    };
+
+    let bytes = path.as_os_str().as_bytes();
+    if bytes.starts_with(b"/memfd:") {
+        return None;
+    }
+
+    strip_deleted(bytes).map(|path| PathBuf::from(OsStr::from_bytes(path)))
+}
+
+/// Total the space held by each process, largest first
+pub fn usage_by_pid(files: &[DeletedFile]) -> Vec<(u32, SpaceUsage)> {
+    usage_by(files, |file| file.pid)
+}
+
+/// Total the space held on each device, largest first
+pub fn usage_by_device(files: &[DeletedFile]) -> Vec<(u64, SpaceUsage)> {
+    usage_by(files, |file| file.dev)
+}
+
+fn usage_by<K: Copy + Eq + Hash, F: Fn(&DeletedFile) -> K>(
+    files: &[DeletedFile],
+    key: F,
+) -> Vec<(K, SpaceUsage)> {
+    let mut usage: Vec<(K, SpaceUsage)> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for file in files {
+        if !seen.insert((key(file), file.dev, file.inode)) {
+            continue;
+        }
+
+        let index = match usage.iter().position(|u| u.0 == key(file)) {
+            Some(index) => index,
+            None => {
+                usage.push((key(file), SpaceUsage::default()));
+                usage.len() - 1
+            }
+        };
+        usage[index].1.files += 1;
+        usage[index].1.size += file.size;
+    }
+
+    usage.sort_by_key(|u| Reverse(u.1.size));
+    usage
+}
+
+/// The device number as `major:minor`, like in /proc/<pid>/mountinfo
+pub fn device_name(dev: u64) -> String {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+
+    format!("{}:{}", major, minor)
+}
+
+/// The device number for `major:minor`, as `stat` reports it
+pub fn make_dev(major: u64, minor: u64) -> u64 {
+    ((major & 0xfff) << 8) | ((major & !0xfff) << 32) | (minor & 0xff) | ((minor & !0xff) << 12)
+}
+
+/// Read the devices and mount points from /proc/<pid>/mountinfo
+///
+/// A table that can not be read is taken as empty.
+fn read_mounts(procfs: &ProcFs, pid: u32) -> Vec<(u64, PathBuf)> {
+    let mountinfo = match procfs.read_pid_file(pid, "mountinfo") {
+        Ok(mountinfo) => mountinfo,
+        Err(_) => {
+            return Vec::new();
+        }
+    };
+
+    parse_mounts(&String::from_utf8_lossy(&mountinfo))
+}
+
+fn parse_mounts(mountinfo: &str) -> Vec<(u64, PathBuf)> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            let colon = fields.get(2)?.find(':')?;
+            let major: u64 = fields[2][..colon].parse().ok()?;
+            let minor: u64 = fields[2][(colon + 1)..].parse().ok()?;
+
+            Some((
+                make_dev(major, minor),
+                PathBuf::from(unescape_mount(fields.get(4)?)),
+            ))
+        })
+        .collect()
+}
+
+/// Undo the octal escapes of spaces and such in mount points
+fn unescape_mount(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let octal = bytes.get((i + 1)..(i + 4));
+        match octal {
+            Some(digits)
+                if bytes[i] == b'\\' && digits.iter().all(|c| (b'0'..b'8').contains(c)) =>
+            {
+                out.push(
+                    digits
+                        .iter()
+                        .fold(0u8, |n, c| n.wrapping_mul(8) + (c - b'0')),
+                );
+                i += 4;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(pid: u32, fd: u32, dev: u64, inode: u64, size: u64) -> DeletedFile {
+        DeletedFile {
+            pid,
+            fd,
+            path: PathBuf::from("/x"),
+            size,
+            dev,
+            inode,
+            mount: None,
+        }
+    }
+
+    #[test]
+    fn test_usage() {
+        let files = [
+            file(10, 3, 1, 100, 50),
+            file(10, 4, 1, 100, 50),
+            file(11, 3, 1, 100, 50),
+            file(11, 4, 2, 100, 70),
+        ];
+
+        assert_eq!(
+            vec![
+                (
+                    11,
+                    SpaceUsage {
+                        files: 2,
+                        size: 120
+                    }
+                ),
+                (10, SpaceUsage { files: 1, size: 50 }),
+            ],
+            usage_by_pid(&files)
+        );
+        assert_eq!(
+            vec![
+                (2, SpaceUsage { files: 1, size: 70 }),
+                (1, SpaceUsage { files: 1, size: 50 }),
+            ],
+            usage_by_device(&files)
+        );
+    }
+
+    #[test]
+    fn test_deleted_path() {
+        assert_eq!(
+            Some(PathBuf::from("/var/log/my app.log")),
+            deleted_path(Path::new("/var/log/my app.log (deleted)"))
+        );
+        assert_eq!(None, deleted_path(Path::new("/var/log/app.log")));
+        assert_eq!(
+            None,
+            deleted_path(Path::new("/memfd:wayland-shm (deleted)"))
+        );
+        assert_eq!(None, deleted_path(Path::new("pipe:[4242]")));
+    }
+
+    #[test]
+    fn test_parse_mounts() {
+        let mounts = parse_mounts(
+            "22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw\n\
+             40 22 8:17 / /mnt/my\\040disk rw - xfs /dev/sdb1 rw\n",
+        );
+
+        assert_eq!(2, mounts.len());
+        assert_eq!("259:2", device_name(mounts[0].0));
+        assert_eq!(PathBuf::from("/mnt/my disk"), mounts[1].1);
+        assert_eq!("8:17", device_name(mounts[1].0));
+    }
+}
@@ -0,0 +1,97 @@
+/// One step in turning a list into another
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit {
+    /// Items at these positions of the old and the new list are equal
+    Same(usize, usize),
+    /// The item at this position of the old list is gone
+    Removed(usize),
+    /// The item at this position of the new list is new
+    Added(usize),
+}
+
+/// Align two lists along their longest common subsequence
+///
+/// Removals come before additions where there is a choice, like in
+/// diff(1). Common items at the ends are matched up front, so that
+/// the quadratic part only covers what actually differs.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+
+    let a = &old[prefix..(old.len() - suffix)];
+    let b = &new[prefix..(new.len() - suffix)];
+
+    /* lengths[i][j] is the length of the common subsequence of a[i..]
+     * and b[j..] */
+    let width = b.len() + 1;
+    let mut lengths = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i * width + j] = if a[i] == b[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|n| Edit::Same(n, n)).collect();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            edits.push(Edit::Same(prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if j == b.len()
+            || (i < a.len() && lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+        {
+            edits.push(Edit::Removed(prefix + i));
+            i += 1;
+        } else {
+            edits.push(Edit::Added(prefix + j));
+            j += 1;
+        }
+    }
+
+    for n in 0..suffix {
+        edits.push(Edit::Same(prefix + a.len() + n, prefix + b.len() + n));
+    }
+
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "c", "x", "d", "y", "e"];
+
+        assert_eq!(
+            vec![
+                Edit::Same(0, 0),
+                Edit::Removed(1),
+                Edit::Same(2, 1),
+                Edit::Added(2),
+                Edit::Same(3, 3),
+                Edit::Added(4),
+                Edit::Same(4, 5)
+            ],
+            diff(&old, &new)
+        );
+    }
+
+    #[test]
+    fn test_diff_replace() {
+        assert_eq!(vec![Edit::Removed(0), Edit::Added(0)], diff(&["a"], &["b"]));
+        assert_eq!(vec![Edit::Added(0)], diff(&[], &["b"]));
+    }
+}
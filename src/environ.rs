@@ -0,0 +1,282 @@
+use std::collections::BTreeSet;
+
+use diff::{diff, Edit};
+use error::MupsError;
+use format::{format_args, ArgFormat};
+use procfs::ProcFs;
+use quote::{quote_arg, Quoting};
+use regex::Regex;
+
+/// Contents of /proc/<pid>/environ
+///
+/// This is the environment the process was started with. Changes the
+/// process makes to its own environment later do not show up here.
+/// The variables are kept as raw bytes in their original order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Environ {
+    entries: Vec<Vec<u8>>,
+}
+
+impl Environ {
+    pub fn parse(environ: &[u8]) -> Environ {
+        let entries = environ
+            .split(|b| *b == 0)
+            .filter(|e| !e.is_empty())
+            .map(|e| e.to_vec())
+            .collect();
+
+        Environ { entries }
+    }
+
+    /// Read and parse /proc/<pid>/environ
+    pub fn read_pid(procfs: &ProcFs, pid: u32) -> Result<Environ, MupsError> {
+        Ok(Environ::parse(&procfs.read_pid_file(pid, "environ")?))
+    }
+
+    /// The `NAME=value` entries
+    pub fn entries(&self) -> &[Vec<u8>] {
+        &self.entries
+    }
+
+    /// Value of a variable, as getenv(3) would find it
+    pub fn get(&self, name: &[u8]) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .map(|e| split_var(e))
+            .find(|&(n, _)| n == name)
+            .and_then(|(_, value)| value)
+    }
+
+    /// Keep only the variables whose name matches one of the patterns
+    pub fn retain_names(&mut self, patterns: &[Regex]) {
+        self.entries.retain(|e| {
+            let name = String::from_utf8_lossy(split_var(e).0);
+            patterns.iter().any(|p| p.is_match(&name))
+        });
+    }
+
+    /// Sort the variables by name
+    ///
+    /// The sort is stable, so if a name appears more than once, the
+    /// entry that getenv(3) would find stays first.
+    pub fn sort(&mut self) {
+        self.entries
+            .sort_by(|a, b| split_var(a).0.cmp(split_var(b).0));
+    }
+}
+
+/// A variable that differs between two environments
+#[derive(Clone, Debug, PartialEq)]
+pub struct VarDiff {
+    pub name: Vec<u8>,
+    /// The value in the first environment, if it was set there
+    pub old: Option<Vec<u8>>,
+    /// The value in the second environment, if it was set there
+    pub new: Option<Vec<u8>>,
+}
+
+/// Compare two environments, variable by variable
+///
+/// Variables are looked up like getenv(3) does, and the differences
+/// come out sorted by name.
+pub fn diff_environ(old: &Environ, new: &Environ) -> Vec<VarDiff> {
+    let names: BTreeSet<&[u8]> = old
+        .entries()
+        .iter()
+        .chain(new.entries())
+        .map(|e| split_var(e))
+        .filter(|&(_, value)| value.is_some())
+        .map(|(name, _)| name)
+        .collect();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (a, b) = (old.get(name), new.get(name));
+            if a == b {
+                return None;
+            }
+            Some(VarDiff {
+                name: name.to_vec(),
+                old: a.map(|v| v.to_vec()),
+                new: b.map(|v| v.to_vec()),
+            })
+        })
+        .collect()
+}
+
+/// Whether the variable holds a colon separated list of directories
+pub fn is_path_like(name: &[u8]) -> bool {
+    name.ends_with(b"PATH") || name.ends_with(b"_DIRS")
+}
+
+/// Lay out differences between environments
+///
+/// Removed values are marked with `-` and added ones with `+`. A
+/// changed list of directories gets a `~` line with the name, and its
+/// elements follow indented, marked the same way.
+pub fn format_environ_diff(diffs: &[VarDiff], quoting: Quoting) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    let line = |out: &mut Vec<u8>, mark: u8, name: &[u8], value: &[u8]| {
+        out.push(mark);
+        out.extend_from_slice(name);
+        out.push(b'=');
+        out.extend_from_slice(&quote_arg(value, quoting));
+        out.push(b'\n');
+    };
+
+    for var in diffs {
+        match (&var.old, &var.new) {
+            (Some(old), Some(new)) if is_path_like(&var.name) => {
+                out.push(b'~');
+                out.extend_from_slice(&var.name);
+                out.push(b'\n');
+
+                let old: Vec<&[u8]> = old.split(|&c| c == b':').collect();
+                let new: Vec<&[u8]> = new.split(|&c| c == b':').collect();
+
+                for edit in diff(&old, &new) {
+                    let (mark, element) = match edit {
+                        Edit::Same(n, _) => (b' ', old[n]),
+                        Edit::Removed(n) => (b'-', old[n]),
+                        Edit::Added(n) => (b'+', new[n]),
+                    };
+                    out.extend_from_slice(b"  ");
+                    out.push(mark);
+                    out.extend_from_slice(&quote_arg(element, quoting));
+                    out.push(b'\n');
+                }
+            }
+            (old, new) => {
+                if let Some(old) = old {
+                    line(&mut out, b'-', &var.name, old);
+                }
+                if let Some(new) = new {
+                    line(&mut out, b'+', &var.name, new);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Split a `NAME=value` entry into the name and the value
+///
+/// Nothing stops a process from putting entries without `=` into the
+/// environment of another; those have no value.
+pub fn split_var(entry: &[u8]) -> (&[u8], Option<&[u8]>) {
+    match entry.iter().position(|b| *b == b'=') {
+        Some(eq) => (&entry[..eq], Some(&entry[(eq + 1)..])),
+        None => (entry, None),
+    }
+}
+
+/// List the variables one per line, with the values quoted as requested
+pub fn format_environ(environ: &Environ, quoting: Quoting) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for entry in environ.entries() {
+        let (name, value) = split_var(entry);
+
+        out.extend_from_slice(name);
+        if let Some(value) = value {
+            out.push(b'=');
+            out.extend_from_slice(&quote_arg(value, quoting));
+        }
+        out.push(b'\n');
+    }
+
+    out
+}
+
+/// Lay out an `env -i` command which runs `args` in the environment
+///
+/// Entries without a value can not be passed through env(1), so they
+/// are left out.
+pub fn format_env_command(environ: &Environ, args: &[Vec<u8>], format: &ArgFormat) -> Vec<u8> {
+    let mut out = b"env -i".to_vec();
+
+    for entry in environ.entries() {
+        if split_var(entry).1.is_some() {
+            out.extend_from_slice(b" \\\n    ");
+            out.extend_from_slice(&quote_arg(entry, format.quoting));
+        }
+    }
+
+    if !args.is_empty() {
+        out.extend_from_slice(b" \\\n    ");
+        out.extend_from_slice(&format_args(args, format));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_environ_parse() {
+        let mut environ = Environ::parse(b"PATH=/bin\0LANG=C\0junk\0LANG=fi_FI\0A=x=y\0");
+
+        assert_eq!(5, environ.entries().len());
+        assert_eq!(Some(&b"C"[..]), environ.get(b"LANG"));
+        assert_eq!(Some(&b"x=y"[..]), environ.get(b"A"));
+        assert_eq!(None, environ.get(b"junk"));
+
+        environ.sort();
+        assert_eq!(
+            vec![
+                b"A=x=y".to_vec(),
+                b"LANG=C".to_vec(),
+                b"LANG=fi_FI".to_vec(),
+                b"PATH=/bin".to_vec(),
+                b"junk".to_vec()
+            ],
+            environ.entries()
+        );
+
+        environ.retain_names(&[
+            Regex::from_glob("L*").unwrap(),
+            Regex::from_glob("j?nk").unwrap(),
+        ]);
+        assert_eq!(3, environ.entries().len());
+    }
+
+    #[test]
+    fn test_diff_environ() {
+        let old = Environ::parse(b"A=1\0PATH=/usr/bin:/bin\0B=2\0LANG=C\0");
+        let new = Environ::parse(b"LANG=C\0PATH=/opt/bin:/usr/bin\0B=3\0C=\0");
+
+        assert_eq!(
+            "-A=1\n-B=2\n+B=3\n+C=''\n~PATH\n  +/opt/bin\n   /usr/bin\n  -/bin\n",
+            String::from_utf8_lossy(&format_environ_diff(
+                &diff_environ(&old, &new),
+                Quoting::Posix
+            ))
+        );
+    }
+
+    #[test]
+    fn test_format_environ() {
+        let environ = Environ::parse(b"PS1=\\u@\\h $ \0EMPTY=\0junk\0");
+
+        assert_eq!(
+            "PS1='\\u@\\h $ '\nEMPTY=''\njunk\n",
+            String::from_utf8_lossy(&format_environ(&environ, Quoting::Posix))
+        );
+    }
+
+    #[test]
+    fn test_format_env_command() {
+        let environ = Environ::parse(b"HOME=/root\0junk\0MSG=a b\0");
+        let args = vec![b"sleep".to_vec(), b"10".to_vec()];
+
+        assert_eq!(
+            "env -i \\\n    HOME=/root \\\n    'MSG=a b' \\\n    sleep \\\n    10",
+            String::from_utf8_lossy(&format_env_command(&environ, &args, &ArgFormat::default()))
+        );
+    }
+}
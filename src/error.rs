@@ -0,0 +1,143 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use shell::ShellSyntaxError;
+
+/// Everything that can go wrong while looking at processes
+#[derive(Debug)]
+pub enum MupsError {
+    /// The process does not exist, or it went away while we were
+    /// looking at it
+    NoSuchProcess(u32),
+    /// No process matched the given selection criteria
+    NoMatchingProcess,
+    /// We are not allowed to read the given file
+    PermissionDenied(PathBuf),
+    /// A file under /proc did not have the expected format
+    MalformedProcFile { path: PathBuf, content: String },
+    /// Input given to us could not be understood
+    BadInput(String),
+    /// Any other I/O error, with the path involved if there was one
+    Io {
+        path: Option<PathBuf>,
+        error: io::Error,
+    },
+}
+
+impl MupsError {
+    /// Exit status that the command line tool should use
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            MupsError::NoSuchProcess(_) | MupsError::NoMatchingProcess => 3,
+            MupsError::PermissionDenied(_) => 4,
+            MupsError::MalformedProcFile { .. } => 5,
+            MupsError::Io { .. } => 6,
+            MupsError::BadInput(_) => 2,
+        }
+    }
+
+    pub(crate) fn from_io<P: AsRef<Path>>(path: P, error: io::Error) -> MupsError {
+        let path = path.as_ref().to_path_buf();
+
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => MupsError::PermissionDenied(path),
+            _ => MupsError::Io {
+                path: Some(path),
+                error,
+            },
+        }
+    }
+
+    /// Interpret a missing file as the process having gone away
+    ///
+    /// Files under /proc/<pid> vanish together with the process, so
+    /// when reading one of them fails with ENOENT or ESRCH, the
+    /// process is no longer there.
+    pub(crate) fn for_pid(self, pid: u32) -> MupsError {
+        const ESRCH: i32 = 3;
+
+        match self {
+            MupsError::Io { ref error, .. }
+                if error.kind() == io::ErrorKind::NotFound
+                    || error.raw_os_error() == Some(ESRCH) =>
+            {
+                MupsError::NoSuchProcess(pid)
+            }
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for MupsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MupsError::NoSuchProcess(pid) => write!(f, "no process with pid {}", pid),
+            MupsError::NoMatchingProcess => write!(f, "no matching processes"),
+            MupsError::PermissionDenied(ref path) => {
+                write!(f, "permission denied reading {}", path.display())
+            }
+            MupsError::MalformedProcFile {
+                ref path,
+                ref content,
+            } => write!(
+                f,
+                "unexpected contents in {}: {:?}",
+                path.display(),
+                content
+            ),
+            MupsError::BadInput(ref message) => write!(f, "{}", message),
+            MupsError::Io {
+                path: Some(ref path),
+                ref error,
+            } => write!(f, "{}: {}", path.display(), error),
+            MupsError::Io {
+                path: None,
+                ref error,
+            } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for MupsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            MupsError::Io { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ShellSyntaxError> for MupsError {
+    fn from(error: ShellSyntaxError) -> MupsError {
+        MupsError::BadInput(error.to_string())
+    }
+}
+
+impl From<io::Error> for MupsError {
+    fn from(error: io::Error) -> MupsError {
+        MupsError::Io { path: None, error }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_for_pid() {
+        let not_found = io::Error::from(io::ErrorKind::NotFound);
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+
+        match MupsError::from_io("/proc/7/stat", not_found).for_pid(7) {
+            MupsError::NoSuchProcess(7) => {}
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        match MupsError::from_io("/proc/7/stat", denied).for_pid(7) {
+            MupsError::PermissionDenied(ref path) => assert_eq!(Path::new("/proc/7/stat"), path),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+}
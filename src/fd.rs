@@ -0,0 +1,308 @@
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use error::MupsError;
+use net::NetTables;
+use procfs::ProcFs;
+
+/// Names of the open(2) flags, without the access mode
+const OPEN_FLAGS: &[(u32, &str)] = &[
+    (0o100, "CREAT"),
+    (0o200, "EXCL"),
+    (0o400, "NOCTTY"),
+    (0o1000, "TRUNC"),
+    (0o2000, "APPEND"),
+    (0o4000, "NONBLOCK"),
+    (0o10000, "DSYNC"),
+    (0o20000, "ASYNC"),
+    (0o40000, "DIRECT"),
+    (0o100000, "LARGEFILE"),
+    (0o200000, "DIRECTORY"),
+    (0o400000, "NOFOLLOW"),
+    (0o1000000, "NOATIME"),
+    (0o2000000, "CLOEXEC"),
+    (0o4000000, "SYNC"),
+    (0o10000000, "PATH"),
+    (0o20000000, "TMPFILE"),
+];
+
+/// What an open file descriptor refers to
+#[derive(Clone, Debug, PartialEq)]
+pub enum FdTarget {
+    Path(PathBuf),
+    Pipe(u64),
+    Socket(u64),
+    /// An eventfd, epoll, inotify or other file without an inode of its own
+    AnonInode(String),
+    /// Anything else, like the `net:[...]` namespace files
+    Other(String),
+}
+
+impl FdTarget {
+    /// Classify the target of a /proc/<pid>/fd symlink
+    pub fn parse(link: &Path) -> FdTarget {
+        let bytes = link.as_os_str().as_bytes();
+
+        if bytes.starts_with(b"/") {
+            return FdTarget::Path(link.to_path_buf());
+        }
+
+        let text = String::from_utf8_lossy(bytes).into_owned();
+        let inode = |prefix: &str| -> Option<u64> {
+            text.strip_prefix(prefix)?.strip_suffix(']')?.parse().ok()
+        };
+
+        if let Some(inode) = inode("pipe:[") {
+            FdTarget::Pipe(inode)
+        } else if let Some(inode) = inode("socket:[") {
+            FdTarget::Socket(inode)
+        } else if let Some(kind) = text.strip_prefix("anon_inode:") {
+            FdTarget::AnonInode(String::from(
+                kind.trim_start_matches('[').trim_end_matches(']'),
+            ))
+        } else {
+            FdTarget::Other(text)
+        }
+    }
+
+    /// A short name for the kind of target
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            FdTarget::Path(_) => "path",
+            FdTarget::Pipe(_) => "pipe",
+            FdTarget::Socket(_) => "socket",
+            FdTarget::AnonInode(_) => "anon_inode",
+            FdTarget::Other(_) => "other",
+        }
+    }
+}
+
+impl fmt::Display for FdTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FdTarget::Path(ref path) => write!(f, "{}", path.display()),
+            FdTarget::Pipe(inode) => write!(f, "pipe:[{}]", inode),
+            FdTarget::Socket(inode) => write!(f, "socket:[{}]", inode),
+            FdTarget::AnonInode(ref kind) => write!(f, "anon_inode:[{}]", kind),
+            FdTarget::Other(ref text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Contents of /proc/<pid>/fdinfo/<fd>
+///
+/// Only the fields common to all kinds of files get their own
+/// members, the rest can be looked up by name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FdInfo {
+    pub pos: u64,
+    pub flags: u32,
+    pub fields: Vec<(String, String)>,
+}
+
+impl FdInfo {
+    pub fn parse(fdinfo: &str) -> Option<FdInfo> {
+        let fields: Vec<(String, String)> = fdinfo
+            .lines()
+            .filter_map(|line| {
+                let colon = line.find(':')?;
+                Some((
+                    String::from(&line[..colon]),
+                    String::from(line[(colon + 1)..].trim()),
+                ))
+            })
+            .collect();
+
+        let get = |name: &str| fields.iter().find(|f| f.0 == name).map(|f| f.1.as_str());
+
+        let pos = get("pos")?.parse().ok()?;
+        let flags = u32::from_str_radix(get("flags")?, 8).ok()?;
+
+        Some(FdInfo { pos, flags, fields })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|f| f.0 == name)
+            .map(|f| f.1.as_str())
+    }
+
+    /// The access mode: `r`, `w` or `rw`
+    pub fn mode(&self) -> &'static str {
+        match self.flags & 3 {
+            0 => "r",
+            1 => "w",
+            _ => "rw",
+        }
+    }
+
+    /// Names of the other flags that are set
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        OPEN_FLAGS
+            .iter()
+            .filter(|&&(bit, _)| self.flags & bit != 0)
+            .map(|&(_, name)| name)
+            .collect()
+    }
+}
+
+/// An open file descriptor of a process
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenFd {
+    pub fd: u32,
+    pub target: FdTarget,
+    /// Missing if the descriptor was closed before it could be read
+    pub info: Option<FdInfo>,
+}
+
+/// Read the open file descriptors of a process
+///
+/// Descriptors closed while they are being listed are left out.
+pub fn read_fds(procfs: &ProcFs, pid: u32) -> Result<Vec<OpenFd>, MupsError> {
+    let mut fds = Vec::new();
+
+    for fd in procfs.fds(pid)? {
+        let target = match procfs.read_link(pid, &format!("fd/{}", fd)) {
+            Ok(target) => FdTarget::parse(&target),
+            Err(MupsError::NoSuchProcess(_)) => {
+                continue;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+
+        fds.push(OpenFd {
+            fd,
+            target,
+            info: read_fdinfo(procfs, pid, fd),
+        });
+    }
+
+    Ok(fds)
+}
+
+pub(crate) fn read_fdinfo(procfs: &ProcFs, pid: u32, fd: u32) -> Option<FdInfo> {
+    let fdinfo = procfs.read_pid_file(pid, &format!("fdinfo/{}", fd)).ok()?;
+
+    FdInfo::parse(&String::from_utf8_lossy(&fdinfo))
+}
+
+/// The targets of all descriptors of all processes we can look at
+///
+/// Each item is a pid, a descriptor number and the target.
+/// Processes whose descriptors we may not read are skipped.
+pub fn all_fds(procfs: &ProcFs) -> Result<Vec<(u32, u32, FdTarget)>, MupsError> {
+    let mut all = Vec::new();
+
+    for pid in procfs.pids()? {
+        let fds = match procfs.fds(pid) {
+            Ok(fds) => fds,
+            Err(MupsError::NoSuchProcess(_)) | Err(MupsError::PermissionDenied(_)) => {
+                continue;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+
+        for fd in fds {
+            if let Ok(target) = procfs.read_link(pid, &format!("fd/{}", fd)) {
+                all.push((pid, fd, FdTarget::parse(&target)));
+            }
+        }
+    }
+
+    Ok(all)
+}
+
+/// Other descriptors with the same pipe or socket as a descriptor
+///
+/// For a pipe these are the other ends, as far as they can be told
+/// from the descriptors that refer to the same pipe. For a TCP or UDP
+/// socket connected within the network namespace, the descriptors of
+/// the socket at the other end are included. Each item is a pid, a
+/// descriptor number and the access mode of the descriptor.
+pub fn peers(
+    procfs: &ProcFs,
+    all: &[(u32, u32, FdTarget)],
+    net: &NetTables,
+    pid: u32,
+    fd: &OpenFd,
+) -> Vec<(u32, u32, &'static str)> {
+    let mut targets = vec![fd.target.clone()];
+
+    match fd.target {
+        FdTarget::Pipe(_) => {}
+        FdTarget::Socket(inode) => {
+            if let Some(peer) = net.peer(inode) {
+                targets.push(FdTarget::Socket(peer));
+            }
+        }
+        _ => {
+            return Vec::new();
+        }
+    }
+
+    all.iter()
+        .filter(|&&(p, f, ref target)| targets.contains(target) && (p, f) != (pid, fd.fd))
+        .map(|&(p, f, _)| {
+            let mode = read_fdinfo(procfs, p, f).map_or("?", |info| info.mode());
+            (p, f, mode)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fd_target_parse() {
+        assert_eq!(
+            FdTarget::Path(PathBuf::from("/var/log/x.log (deleted)")),
+            FdTarget::parse(Path::new("/var/log/x.log (deleted)"))
+        );
+        assert_eq!(
+            FdTarget::Pipe(1234),
+            FdTarget::parse(Path::new("pipe:[1234]"))
+        );
+        assert_eq!(
+            FdTarget::Socket(99),
+            FdTarget::parse(Path::new("socket:[99]"))
+        );
+        assert_eq!(
+            FdTarget::AnonInode(String::from("eventfd")),
+            FdTarget::parse(Path::new("anon_inode:[eventfd]"))
+        );
+        assert_eq!(
+            FdTarget::AnonInode(String::from("inotify")),
+            FdTarget::parse(Path::new("anon_inode:inotify"))
+        );
+        assert_eq!(
+            FdTarget::Other(String::from("net:[4026531840]")),
+            FdTarget::parse(Path::new("net:[4026531840]"))
+        );
+        assert_eq!(
+            "anon_inode:[inotify]",
+            FdTarget::AnonInode(String::from("inotify")).to_string()
+        );
+    }
+
+    #[test]
+    fn test_fdinfo_parse() {
+        let info = FdInfo::parse("pos:\t42\nflags:\t02106002\nmnt_id:\t25\nino:\t7\n").unwrap();
+
+        assert_eq!(42, info.pos);
+        assert_eq!("rw", info.mode());
+        assert_eq!(
+            vec!["APPEND", "NONBLOCK", "LARGEFILE", "CLOEXEC"],
+            info.flag_names()
+        );
+        assert_eq!(Some("25"), info.get("mnt_id"));
+
+        assert_eq!(None, FdInfo::parse("flags:\t0\n"));
+    }
+}
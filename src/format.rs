@@ -0,0 +1,347 @@
+use std::os::unix::ffi::OsStrExt;
+
+use diff::{diff, Edit};
+use profile::{arrange_groups, group_args, is_path_list, Profile};
+use quote::{quote_arg, Quoting};
+use response::Expanded;
+use shell::{split_words, ShellSyntaxError};
+
+/// How to lay out an argument list
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArgFormat {
+    pub quoting: Quoting,
+    /// Keep options on the same line with their values
+    pub grouping: bool,
+    /// Syntax of the command line, or `None` to guess it from argv[0]
+    pub profile: Option<Profile>,
+    /// Read in `@file` arguments when printing the args of a process
+    pub expand_response_files: bool,
+}
+
+impl Default for ArgFormat {
+    fn default() -> ArgFormat {
+        ArgFormat {
+            quoting: Quoting::Posix,
+            grouping: true,
+            profile: None,
+            expand_response_files: false,
+        }
+    }
+}
+
+/// Join arguments so that each of them goes on its own line
+pub fn format_arglist(args: &[&str]) -> String {
+    args.join(" \\\n    ")
+}
+
+/// Lay out arguments with one argument or option per line
+///
+/// The arguments are quoted as requested, but otherwise left as
+/// they are, so the result is not necessarily valid UTF-8. With
+/// grouping, related options are also gathered together as the
+/// profile of the tool suggests.
+pub fn format_args(args: &[Vec<u8>], format: &ArgFormat) -> Vec<u8> {
+    let profile = profile_for(args, format);
+    let mut out = Vec::new();
+
+    for (n, group) in arg_groups(args, profile, format).iter().enumerate() {
+        if n > 0 {
+            out.extend_from_slice(b" \\\n    ");
+        }
+        write_group(&mut out, group, profile, format);
+    }
+
+    out
+}
+
+/// Lay out arguments of which some were read from response files
+///
+/// Comment lines before the command tell which files were read, and
+/// the arguments from a file are indented one step deeper than the
+/// arguments around the `@file` argument.
+pub fn format_expanded(expanded: &Expanded, format: &ArgFormat) -> Vec<u8> {
+    let args = &expanded.args;
+    let profile = profile_for(args, format);
+    let mut out = Vec::new();
+
+    for file in &expanded.files {
+        out.extend_from_slice(b"# ");
+        for _ in 0..file.depth {
+            out.extend_from_slice(b"  ");
+        }
+        out.extend_from_slice(&quote_arg(&file.arg, format.quoting));
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(&quote_arg(file.path.as_os_str().as_bytes(), format.quoting));
+        out.push(b'\n');
+    }
+
+    let mut bounds = vec![0, args.len()];
+    for file in &expanded.files {
+        bounds.push(file.start);
+        bounds.push(file.start + file.len);
+    }
+    bounds.sort();
+    bounds.dedup();
+
+    let mut first = true;
+
+    for window in bounds.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        let depth = expanded
+            .files
+            .iter()
+            .filter(|f| f.start <= start && start < f.start + f.len)
+            .count();
+
+        /* Each piece is grouped on its own, as if it followed argv[0] */
+        let piece: Vec<Vec<u8>> = if start == 0 {
+            args[..end].to_vec()
+        } else {
+            let mut piece = vec![args[0].clone()];
+            piece.extend_from_slice(&args[start..end]);
+            piece
+        };
+        let skip = if start == 0 { 0 } else { 1 };
+
+        for group in &arg_groups(&piece, profile, format)[skip..] {
+            if !first {
+                out.extend_from_slice(b" \\\n    ");
+                for _ in 0..depth {
+                    out.extend_from_slice(b"    ");
+                }
+            }
+            write_group(&mut out, group, profile, format);
+            first = false;
+        }
+    }
+
+    out
+}
+
+/// Lay out the differences between two argument lists
+///
+/// Both lists are laid out one argument or option per line, and the
+/// lines are aligned like in a unified diff: lines only in `old` are
+/// marked with `-`, lines only in `new` with `+`. A changed argument
+/// shows up as a removed and an added line.
+pub fn format_args_diff(old: &[Vec<u8>], new: &[Vec<u8>], format: &ArgFormat) -> Vec<u8> {
+    let lines = |args: &[Vec<u8>]| -> Vec<Vec<u8>> {
+        let profile = profile_for(args, format);
+        arg_groups(args, profile, format)
+            .iter()
+            .map(|group| {
+                let mut line = Vec::new();
+                write_group(&mut line, group, profile, format);
+                line
+            })
+            .collect()
+    };
+
+    let old = lines(old);
+    let new = lines(new);
+    let mut out = Vec::new();
+
+    for edit in diff(&old, &new) {
+        let (mark, n, side) = match edit {
+            Edit::Same(n, _) => (b' ', n, &old),
+            Edit::Removed(n) => (b'-', n, &old),
+            Edit::Added(n) => (b'+', n, &new),
+        };
+
+        out.push(mark);
+        if n > 0 {
+            out.extend_from_slice(b"    ");
+        }
+        out.extend_from_slice(&side[n]);
+        if n + 1 < side.len() {
+            out.extend_from_slice(b" \\");
+        }
+        out.push(b'\n');
+    }
+
+    out
+}
+
+/// Format a number of bytes for people, like `du -h`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["K", "M", "G", "T", "P", "E"];
+
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if size < 10.0 {
+        format!("{:.1}{}", size, UNITS[unit])
+    } else {
+        format!("{:.0}{}", size, UNITS[unit])
+    }
+}
+
+fn arg_groups<'a>(args: &'a [Vec<u8>], profile: Profile, format: &ArgFormat) -> Vec<&'a [Vec<u8>]> {
+    if format.grouping {
+        arrange_groups(profile, &group_args(profile, args))
+    } else {
+        args.chunks(1).collect()
+    }
+}
+
+fn profile_for(args: &[Vec<u8>], format: &ArgFormat) -> Profile {
+    match (format.profile, args.first()) {
+        (Some(profile), _) => profile,
+        (None, Some(argv0)) => Profile::detect(argv0),
+        (None, None) => Profile::Generic,
+    }
+}
+
+fn write_group(out: &mut Vec<u8>, group: &[Vec<u8>], profile: Profile, format: &ArgFormat) {
+    if format.grouping && is_path_list(profile, group) {
+        out.extend_from_slice(&quote_arg(&group[0], format.quoting));
+        out.push(b' ');
+        write_path_list(out, &group[1], format.quoting);
+        return;
+    }
+
+    for (m, arg) in group.iter().enumerate() {
+        if m > 0 {
+            out.push(b' ');
+        }
+        out.extend_from_slice(&quote_arg(arg, format.quoting));
+    }
+}
+
+/// Write a colon separated list of paths with one path per line
+///
+/// The line breaks are escaped inside the word, so that a shell still
+/// reads the list as one argument. The continuation lines can not be
+/// indented, since the indentation would end the word.
+fn write_path_list(out: &mut Vec<u8>, list: &[u8], quoting: Quoting) {
+    for (n, path) in list.split(|&c| c == b':').enumerate() {
+        if n > 0 {
+            out.extend_from_slice(b":\\\n");
+        }
+        out.extend_from_slice(&quote_arg(path, quoting));
+    }
+}
+
+/// Reformat a command line given as text for easier viewing
+///
+/// The command line is split into words like a shell would do it,
+/// and the words are quoted again as needed.
+pub fn prettify(stuff_in: &str, format: &ArgFormat) -> Result<String, ShellSyntaxError> {
+    let words = split_words(stuff_in)?;
+
+    Ok(String::from_utf8_lossy(&format_args(&words, format)).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pretty_format() -> ArgFormat {
+        ArgFormat {
+            quoting: Quoting::Bash,
+            grouping: true,
+            profile: None,
+            expand_response_files: false,
+        }
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!("0", format_size(0));
+        assert_eq!("1023", format_size(1023));
+        assert_eq!("1.0K", format_size(1024));
+        assert_eq!("1.5M", format_size(1536 * 1024));
+        assert_eq!("200G", format_size(200 << 30));
+    }
+
+    #[test]
+    fn test_format_arglist_1() {
+        let args = ["gcc", "-c", "hello.c"];
+
+        let expected_pretty = include_str!("td/format_arglist_1.txt");
+        let prettified = format_arglist(&args);
+
+        assert_eq!(expected_pretty, &prettified);
+    }
+
+    #[test]
+    fn test_prettify_1() {
+        let text = include_str!("td/prettify_1_orig.txt");
+        let expected_pretty = include_str!("td/prettify_1_pretty.txt");
+
+        let prettified = prettify(text, &pretty_format()).unwrap();
+
+        assert_eq!(expected_pretty, &prettified);
+    }
+
+    #[test]
+    fn test_prettify_2() {
+        let text = include_str!("td/prettify_2_orig.txt");
+        let expected_pretty = include_str!("td/prettify_2_pretty.txt");
+
+        let prettified = prettify(text, &pretty_format()).unwrap();
+
+        assert_eq!(expected_pretty, &prettified);
+        assert_eq!(
+            expected_pretty,
+            &prettify(&prettified, &pretty_format()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_prettify_compiler() {
+        let text = include_str!("td/prettify_3_orig.txt");
+        let expected_pretty = include_str!("td/prettify_3_pretty.txt");
+
+        let prettified = prettify(text, &pretty_format()).unwrap();
+
+        assert_eq!(expected_pretty, &prettified);
+    }
+
+    #[test]
+    fn test_prettify_java() {
+        let text = include_str!("td/prettify_4_orig.txt");
+        let expected_pretty = include_str!("td/prettify_4_pretty.txt");
+
+        let prettified = prettify(text, &pretty_format()).unwrap();
+
+        assert_eq!(expected_pretty, &prettified);
+        assert_eq!(
+            expected_pretty,
+            &prettify(&prettified, &pretty_format()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_prettify_profile_override() {
+        let format = ArgFormat {
+            profile: Some(Profile::Generic),
+            ..pretty_format()
+        };
+
+        assert_eq!(
+            "java \\\n    -cp \\\n    a.jar:b.jar",
+            prettify("java -cp a.jar:b.jar", &format).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_args_diff() {
+        let args = |line: &str| split_words(line).unwrap();
+        let old = args("gcc -O2 -c -o a.o x.c");
+        let new = args("gcc -c -o b.o -g x.c");
+
+        assert_eq!(
+            " gcc \\\n-    -O2 \\\n     -c \\\n-    -o a.o \\\n+    -o b.o \\\n+    -g \\\n     x.c\n",
+            String::from_utf8_lossy(&format_args_diff(&old, &new, &ArgFormat::default()))
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use error::MupsError;
+use fd::{read_fdinfo, FdTarget};
+use maps::read_maps;
+use procfs::{strip_deleted, ProcFs};
+
+/// How a process holds on to a file
+#[derive(Clone, Debug, PartialEq)]
+pub enum Access {
+    /// An open descriptor, with its access mode
+    Fd(u32, &'static str),
+    Cwd,
+    Root,
+    Exe,
+    /// Mapped into memory, like shared libraries
+    Mapped,
+}
+
+impl Access {
+    /// A short name for the kind of access
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Access::Fd(..) => "fd",
+            Access::Cwd => "cwd",
+            Access::Root => "root",
+            Access::Exe => "exe",
+            Access::Mapped => "mapped",
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Access::Fd(fd, mode) => write!(f, "fd {} {}", fd, mode),
+            _ => write!(f, "{}", self.kind()),
+        }
+    }
+}
+
+/// A process holding on to a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Holder {
+    pub pid: u32,
+    pub access: Access,
+    /// The file, without the ` (deleted)` suffix the kernel adds
+    pub path: PathBuf,
+    pub deleted: bool,
+}
+
+/// Find the processes that hold a file or anything below a directory
+///
+/// A file is held when a process has it open, uses it as working or
+/// root directory, runs it or has it mapped into memory. Processes
+/// that may not be looked at, or that exit meanwhile, are skipped.
+pub fn find_holders(procfs: &ProcFs, path: &Path) -> Result<Vec<Holder>, MupsError> {
+    let mut holders = Vec::new();
+
+    for pid in procfs.pids()? {
+        match holders_of_pid(procfs, pid, path) {
+            Ok(found) => holders.extend(found),
+            Err(MupsError::NoSuchProcess(_)) | Err(MupsError::PermissionDenied(_)) => {}
+            Err(e) => {
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(holders)
+}
+
+fn holders_of_pid(procfs: &ProcFs, pid: u32, path: &Path) -> Result<Vec<Holder>, MupsError> {
+    let mut found = Vec::new();
+
+    let mut check = |target: &Path, access: Access| {
+        let bytes = target.as_os_str().as_bytes();
+        let (target, deleted) = match strip_deleted(bytes) {
+            Some(stripped) => (Path::new(OsStr::from_bytes(stripped)), true),
+            None => (target, false),
+        };
+
+        if target.starts_with(path) {
+            found.push(Holder {
+                pid,
+                access,
+                path: target.to_path_buf(),
+                deleted,
+            });
+        }
+    };
+
+    for &(name, ref access) in &[
+        ("cwd", Access::Cwd),
+        ("root", Access::Root),
+        ("exe", Access::Exe),
+    ] {
+        if let Ok(target) = procfs.read_link(pid, name) {
+            check(&target, access.clone());
+        }
+    }
+
+    for fd in procfs.fds(pid)? {
+        if let Ok(target) = procfs.read_link(pid, &format!("fd/{}", fd)) {
+            if let FdTarget::Path(target) = FdTarget::parse(&target) {
+                let mode = read_fdinfo(procfs, pid, fd).map_or("?", |info| info.mode());
+                check(&target, Access::Fd(fd, mode));
+            }
+        }
+    }
+
+    /* A library is mapped several times, once for each part */
+    let mut mapped: Vec<PathBuf> = read_maps(procfs, pid)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|region| region.path)
+        .filter(|p| p.as_os_str().as_bytes().starts_with(b"/"))
+        .collect();
+    mapped.sort();
+    mapped.dedup();
+
+    for target in mapped {
+        check(&target, Access::Mapped);
+    }
+
+    Ok(found)
+}
@@ -0,0 +1,305 @@
+use std::fmt;
+
+use error::MupsError;
+use procfs::ProcFs;
+use stat::ProcStat;
+
+/// A JSON value, for machine readable output
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Start an object, to be filled in with `field`
+    pub fn object() -> Json {
+        Json::Object(Vec::new())
+    }
+
+    /// Add a field to an object
+    ///
+    /// Calling this on anything else than an object does nothing.
+    pub fn field<K: Into<String>, V: Into<Json>>(mut self, key: K, value: V) -> Json {
+        if let Json::Object(ref mut fields) = self {
+            fields.push((key.into(), value.into()));
+        }
+        self
+    }
+
+    /// Format the value over multiple lines with indentation
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, indent: usize) {
+        let (items, open, close) = match *self {
+            Json::Array(ref items) if !items.is_empty() => (items.len(), '[', ']'),
+            Json::Object(ref fields) if !fields.is_empty() => (fields.len(), '{', '}'),
+            _ => {
+                out.push_str(&self.to_string());
+                return;
+            }
+        };
+
+        out.push(open);
+
+        for n in 0..items {
+            out.push('\n');
+            out.push_str(&"  ".repeat(indent + 1));
+
+            match *self {
+                Json::Array(ref items) => items[n].write_pretty(out, indent + 1),
+                Json::Object(ref fields) => {
+                    out.push_str(&Json::Str(fields[n].0.clone()).to_string());
+                    out.push_str(": ");
+                    fields[n].1.write_pretty(out, indent + 1);
+                }
+                _ => unreachable!(),
+            }
+
+            if n + 1 < items {
+                out.push(',');
+            }
+        }
+
+        out.push('\n');
+        out.push_str(&"  ".repeat(indent));
+        out.push(close);
+    }
+}
+
+/// Compact formatting, all on one line
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Int(n) => write!(f, "{}", n),
+            Json::Str(ref s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Json::Array(ref items) => {
+                write!(f, "[")?;
+                for (n, item) in items.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(ref fields) => {
+                write!(f, "{{")?;
+                for (n, (key, value)) in fields.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", Json::Str(key.clone()), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Json {
+        Json::Int(n)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Json {
+        Json::Int(i64::from(n))
+    }
+}
+
+impl<'a> From<&'a str> for Json {
+    fn from(s: &'a str) -> Json {
+        Json::Str(String::from(s))
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::Str(s)
+    }
+}
+
+impl From<char> for Json {
+    fn from(c: char) -> Json {
+        Json::Str(c.to_string())
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(items: Vec<T>) -> Json {
+        Json::Array(items.into_iter().map(|i| i.into()).collect())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        match value {
+            Some(v) => v.into(),
+            None => Json::Null,
+        }
+    }
+}
+
+/// Arguments or other strings that may not be valid UTF-8
+///
+/// The strings are given as `key`, with invalid UTF-8 replaced by
+/// U+FFFD. If any of them was not valid UTF-8, the exact bytes of all
+/// of them are also given base64 encoded as `key_base64`.
+pub fn bytes_fields(object: Json, key: &str, items: &[Vec<u8>]) -> Json {
+    let lossy: Vec<String> = items
+        .iter()
+        .map(|i| String::from_utf8_lossy(i).into_owned())
+        .collect();
+
+    let object = object.field(key, lossy);
+
+    if items.iter().all(|i| ::std::str::from_utf8(i).is_ok()) {
+        return object;
+    }
+
+    let encoded: Vec<String> = items.iter().map(|i| base64(i)).collect();
+
+    object.field(format!("{}_base64", key), encoded)
+}
+
+/// A single string that may not be valid UTF-8, like `bytes_fields`
+pub fn bytes_field(object: Json, key: &str, item: &[u8]) -> Json {
+    let object = object.field(key, String::from_utf8_lossy(item).into_owned());
+
+    if ::std::str::from_utf8(item).is_ok() {
+        return object;
+    }
+
+    object.field(format!("{}_base64", key), base64(item))
+}
+
+/// Describe a process with its pid, ppid, state, comm and argv
+///
+/// A process that no longer exists is described only by its pid
+/// and `"exited": true`.
+pub fn process_json(procfs: &ProcFs, pid: u32) -> Result<Json, MupsError> {
+    let result = ProcStat::read_pid(procfs, pid).and_then(|stat| {
+        let argv = procfs.read_cmdline(pid)?;
+        Ok((stat, argv))
+    });
+
+    let (stat, argv) = match result {
+        Ok(r) => r,
+        Err(MupsError::NoSuchProcess(_)) => {
+            return Ok(Json::object().field("pid", pid).field("exited", true));
+        }
+        Err(e) => {
+            return Err(e);
+        }
+    };
+
+    let object = Json::object()
+        .field("pid", stat.pid)
+        .field("ppid", stat.ppid)
+        .field("state", stat.state)
+        .field("comm", stat.comm);
+
+    Ok(bytes_fields(object, "argv", &argv))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_format() {
+        let value = Json::object()
+            .field("pid", 42u32)
+            .field("comm", "a \"b\"\n")
+            .field("argv", vec!["x", "y"])
+            .field("none", Json::Array(Vec::new()));
+
+        assert_eq!(
+            r#"{"pid":42,"comm":"a \"b\"\n","argv":["x","y"],"none":[]}"#,
+            value.to_string()
+        );
+        assert_eq!(
+            "{\n  \"pid\": 42,\n  \"comm\": \"a \\\"b\\\"\\n\",\n  \"argv\": [\n    \"x\",\n    \"y\"\n  ],\n  \"none\": []\n}",
+            value.pretty()
+        );
+    }
+
+    #[test]
+    fn test_bytes_fields() {
+        let valid = bytes_fields(Json::object(), "argv", &[b"ls".to_vec()]);
+        assert_eq!(r#"{"argv":["ls"]}"#, valid.to_string());
+
+        let invalid = bytes_fields(
+            Json::object(),
+            "argv",
+            &[b"ls".to_vec(), b"\xff\ta".to_vec()],
+        );
+        assert_eq!(
+            "{\"argv\":[\"ls\",\"\u{fffd}\\ta\"],\"argv_base64\":[\"bHM=\",\"/wlh\"]}",
+            invalid.to_string()
+        );
+
+        let invalid = bytes_field(Json::object(), "arg", b"\xff\ta");
+        assert_eq!(
+            "{\"arg\":\"\u{fffd}\\ta\",\"arg_base64\":\"/wlh\"}",
+            invalid.to_string()
+        );
+    }
+}
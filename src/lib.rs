@@ -0,0 +1,76 @@
+//! Finding out things about processes
+//!
+//! This is the library behind the `mups` command line tool. It reads
+//! process information from /proc and formats argument lists for
+//! easier viewing.
+//!
+//! ```no_run
+//! let procfs = mups::ProcFs::default();
+//!
+//! for pid in procfs.ancestors(1).unwrap().iter().rev() {
+//!     let stat = mups::ProcStat::read_pid(&procfs, *pid).unwrap();
+//!     println!("{} {} [{}]", stat.pid, stat.comm, stat.state);
+//! }
+//! ```
+
+mod deleted;
+mod diff;
+mod environ;
+mod error;
+mod fd;
+mod format;
+mod holders;
+mod json;
+mod limits;
+mod maps;
+mod net;
+mod procfs;
+mod profile;
+mod quote;
+mod regex;
+mod repro;
+mod response;
+mod select;
+mod shell;
+mod stale;
+mod stat;
+mod status;
+mod tree;
+
+pub use deleted::{
+    device_name, find_deleted, usage_by_device, usage_by_pid, DeletedFile, SpaceUsage,
+};
+pub use diff::{diff, Edit};
+pub use environ::{
+    diff_environ, format_env_command, format_environ, format_environ_diff, is_path_like, split_var,
+    Environ, VarDiff,
+};
+pub use error::MupsError;
+pub use fd::{all_fds, peers, read_fds, FdInfo, FdTarget, OpenFd};
+pub use format::{
+    format_arglist, format_args, format_args_diff, format_expanded, format_size, prettify,
+    ArgFormat,
+};
+pub use holders::{find_holders, Access, Holder};
+pub use json::{bytes_field, bytes_fields, process_json, Json};
+pub use limits::{Limit, ProcLimits};
+pub use maps::{
+    group_regions, parse_smaps, read_maps, read_smaps, read_smaps_rollup, MapGroup, MapRegion,
+    MemUsage, RegionKind,
+};
+pub use net::{
+    parse_inet, parse_unix, socket_owners, InetSocket, NetTables, Protocol, SocketInfo,
+    SocketOwner, SocketQuery, UnixSocket,
+};
+pub use procfs::ProcFs;
+pub use profile::{arrange_groups, group_args, Profile};
+pub use quote::{quote_arg, Quoting};
+pub use regex::{Regex, RegexError};
+pub use repro::repro_script;
+pub use response::{expand_response_files, split_response_file, Expanded, ResponseFile, Syntax};
+pub use select::{lookup_uid, Pick, Selector};
+pub use shell::{split_commands, split_words, ShellSyntaxError};
+pub use stale::{find_stale, service_of, StaleFile, StaleProcess, Staleness};
+pub use stat::ProcStat;
+pub use status::ProcStatus;
+pub use tree::{ProcessTree, TreeOptions};
@@ -0,0 +1,122 @@
+use error::MupsError;
+use procfs::{string_from_path, ProcFs};
+
+/// One resource limit, with `None` standing for unlimited
+#[derive(Clone, Debug, PartialEq)]
+pub struct Limit {
+    /// The description used by the kernel, like `Max open files`
+    pub name: String,
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+    /// Unit of the values, empty for plain numbers
+    pub units: String,
+}
+
+/// Contents of /proc/<pid>/limits
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcLimits {
+    pub limits: Vec<Limit>,
+}
+
+impl ProcLimits {
+    /// Parse the table of limits
+    ///
+    /// The names contain spaces and some limits have no unit, so the
+    /// values are picked from the end of each line.
+    pub fn parse(limits: &str) -> Option<ProcLimits> {
+        let mut parsed = Vec::new();
+
+        for line in limits.lines().skip(1) {
+            let mut words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+
+            let value = |word: &str| -> Option<Option<u64>> {
+                if word == "unlimited" {
+                    Some(None)
+                } else {
+                    word.parse().ok().map(Some)
+                }
+            };
+
+            let units = match words.last() {
+                Some(&word) if value(word).is_none() => {
+                    words.pop();
+                    String::from(word)
+                }
+                _ => String::new(),
+            };
+
+            let hard = value(words.pop()?)?;
+            let soft = value(words.pop()?)?;
+
+            if words.is_empty() {
+                return None;
+            }
+
+            parsed.push(Limit {
+                name: words.join(" "),
+                soft,
+                hard,
+                units,
+            });
+        }
+
+        Some(ProcLimits { limits: parsed })
+    }
+
+    /// Read and parse /proc/<pid>/limits
+    pub fn read_pid(procfs: &ProcFs, pid: u32) -> Result<ProcLimits, MupsError> {
+        let path = procfs.pid_path(pid, "limits");
+        let limits = string_from_path(&path).map_err(|e| e.for_pid(pid))?;
+
+        match ProcLimits::parse(&limits) {
+            Some(limits) => Ok(limits),
+            None => Err(MupsError::MalformedProcFile {
+                path,
+                content: limits,
+            }),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Limit> {
+        self.limits.iter().find(|l| l.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_parse() {
+        let limits = ProcLimits::parse(include_str!("td/limits_1.txt")).unwrap();
+
+        assert_eq!(16, limits.limits.len());
+        assert_eq!(
+            Some(&Limit {
+                name: String::from("Max open files"),
+                soft: Some(1024),
+                hard: Some(524288),
+                units: String::from("files"),
+            }),
+            limits.get("Max open files")
+        );
+        assert_eq!(
+            Some(&Limit {
+                name: String::from("Max nice priority"),
+                soft: Some(0),
+                hard: Some(0),
+                units: String::new(),
+            }),
+            limits.get("Max nice priority")
+        );
+        assert_eq!(None, limits.get("Max cpu time").unwrap().soft);
+
+        assert_eq!(
+            None,
+            ProcLimits::parse("Limit\nMax open files 1024 files\n")
+        );
+    }
+}
@@ -1,252 +1,1657 @@
+#[macro_use]
 extern crate clap;
+extern crate mups;
 
-use std::fs::File;
-use std::io::{stdin, stdout};
-use std::path::Path;
+use std::cmp::Reverse;
+use std::env;
+use std::fs;
+use std::io::{stdin, stdout, Write};
+use std::path::PathBuf;
+use std::process;
 
-use clap::{App, Arg, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
 
-#[allow(dead_code)]
-struct ProcStat {
-    comm: String,
-    pid: u32,
-    ppid: u32,
-    state: char,
+use mups::{
+    all_fds, bytes_field, bytes_fields, device_name, diff, diff_environ, expand_response_files,
+    find_deleted, find_holders, find_stale, format_args_diff, format_env_command, format_environ,
+    format_environ_diff, format_size, group_regions, lookup_uid, peers, prettify, process_json,
+    read_fds, read_maps, read_smaps, read_smaps_rollup, repro_script, service_of, socket_owners,
+    split_commands, usage_by_device, usage_by_pid, Access, ArgFormat, DeletedFile, Edit, Environ,
+    FdTarget, Json, MemUsage, MupsError, NetTables, OpenFd, Pick, ProcFs, ProcStat, ProcessTree,
+    Profile, Protocol, Quoting, Regex, Selector, SocketInfo, SocketQuery, TreeOptions,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Text,
+    Json,
+    Ndjson,
 }
 
-impl ProcStat {
-    pub fn read_pid(pid: u32) -> Option<ProcStat> {
-        let stat = {
-            let path = format!("/proc/{}/stat", pid);
-            match string_from_path(&path) {
-                Some(s) => s,
-                None => {
-                    return None;
-                }
+fn main() {
+    let matches = App::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Joonas Sarajärvi <muep@iki.fi>")
+        .about("A yet another process info tool")
+        .arg(
+            Arg::with_name("proc-root")
+                .long("proc-root")
+                .help("read process information from DIR instead of /proc")
+                .value_name("DIR")
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("args")
+                .about("Print out args of running processes")
+                .args(&selector_args())
+                .arg(format_arg())
+                .arg(quoting_arg())
+                .arg(no_group_arg())
+                .arg(profile_arg())
+                .arg(
+                    Arg::with_name("expand-response-files")
+                        .long("expand-response-files")
+                        .help("show the contents of @file arguments in their place, for programs that read them"),
+                )
+                .arg(diff_arg(
+                    "show how the args of one process differ from another",
+                ))
+                .group(selector_group().arg("diff")),
+        )
+        .subcommand(
+            SubCommand::with_name("children")
+                .about("Print out args of all descendants of a running process")
+                .arg(
+                    Arg::with_name("pid")
+                        .short("p")
+                        .help("select process by id")
+                        .value_name("PID")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short("t")
+                        .long("threads")
+                        .help("also list the threads of each process"),
+                )
+                .arg(format_arg())
+                .arg(quoting_arg())
+                .arg(no_group_arg())
+                .arg(profile_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("deleted")
+                .about("Print out deleted files that are still open, by space held")
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("env")
+                .about("Print out the environment running processes were started with")
+                .args(&selector_args())
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .help("sort the variables by name"),
+                )
+                .arg(
+                    Arg::with_name("var")
+                        .long("var")
+                        .help("only show variables with a name matching GLOB")
+                        .value_name("GLOB")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("command")
+                        .long("command")
+                        .help("print an env -i command running the process in this environment"),
+                )
+                .arg(diff_arg(
+                    "show how the environment of one process differs from another",
+                ))
+                .arg(format_arg())
+                .arg(quoting_arg())
+                .arg(no_group_arg())
+                .arg(profile_arg())
+                .group(selector_group().arg("diff")),
+        )
+        .subcommand(
+            SubCommand::with_name("fds")
+                .about("List the open file descriptors of a running process")
+                .arg(
+                    Arg::with_name("pid")
+                        .short("p")
+                        .help("select process by id")
+                        .value_name("PID")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("maps")
+                .about("Summarize the memory map of a running process")
+                .arg(
+                    Arg::with_name("pid")
+                        .short("p")
+                        .help("select process by id")
+                        .value_name("PID")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("smaps")
+                        .long("smaps")
+                        .help("read smaps to show RSS, PSS and swap"),
+                )
+                .arg(
+                    Arg::with_name("regions")
+                        .long("regions")
+                        .help("list each region instead of grouping by file"),
+                )
+                .arg(
+                    Arg::with_name("rollup")
+                        .long("rollup")
+                        .help("only show the total RSS, PSS and swap, from smaps_rollup")
+                        .conflicts_with_all(&["smaps", "regions"]),
+                )
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("port")
+                .about("Print out args and parents of processes holding a socket")
+                .arg(
+                    Arg::with_name("port")
+                        .help("local TCP or UDP port")
+                        .value_name("PORT"),
+                )
+                .arg(
+                    Arg::with_name("udp")
+                        .long("udp")
+                        .help("look for a UDP port instead of a TCP one")
+                        .requires("port"),
+                )
+                .arg(
+                    Arg::with_name("unix")
+                        .long("unix")
+                        .help("look for a unix socket bound to this path")
+                        .value_name("PATH")
+                        .takes_value(true),
+                )
+                .group(
+                    ArgGroup::with_name("socket")
+                        .args(&["port", "unix"])
+                        .required(true),
+                )
+                .arg(format_arg())
+                .arg(quoting_arg())
+                .arg(no_group_arg())
+                .arg(profile_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("prettify")
+                .about("Reprint an argument list for easier viewing")
+                .arg(no_group_arg())
+                .arg(
+                    Arg::with_name("diff")
+                        .long("diff")
+                        .help("compare two command lines, given on separate lines"),
+                )
+                .arg(profile_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("repro")
+                .about("Print a script that starts a running process again the same way")
+                .arg(
+                    Arg::with_name("pid")
+                        .short("p")
+                        .help("select process by id")
+                        .value_name("PID")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    /* Without quoting, the script would not run the
+                     * same arguments */
+                    Arg::with_name("quoting")
+                        .long("quoting")
+                        .help("how to quote arguments in the script")
+                        .value_name("MODE")
+                        .takes_value(true)
+                        .possible_values(&["posix", "bash"])
+                        .default_value("posix"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stale")
+                .about("Print out processes running deleted or replaced executables or libraries")
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("tree")
+                .about("Print out a tree of running processes")
+                .arg(
+                    Arg::with_name("pid")
+                        .short("p")
+                        .help("start the tree from this process instead of pid 1")
+                        .value_name("PID")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .help("show at most this many levels below the root")
+                        .value_name("N")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("collapse")
+                        .short("c")
+                        .long("collapse")
+                        .help("show identical siblings only once"),
+                )
+                .arg(
+                    Arg::with_name("args")
+                        .short("a")
+                        .long("args")
+                        .help("show the arguments of each process"),
+                )
+                .arg(
+                    Arg::with_name("ascii")
+                        .long("ascii")
+                        .help("draw the tree with plain ASCII characters"),
+                )
+                .arg(format_arg())
+                .arg(quoting_arg())
+                .arg(no_group_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("whatps")
+                .about("Print out args and parents of running processes")
+                .args(&selector_args())
+                .arg(format_arg())
+                .arg(quoting_arg())
+                .arg(no_group_arg())
+                .arg(profile_arg())
+                .group(selector_group()),
+        )
+        .subcommand(
+            SubCommand::with_name("who-has")
+                .about("Print out args and parents of processes holding a file")
+                .arg(
+                    Arg::with_name("file")
+                        .help("file, or directory to look below")
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(format_arg())
+                .arg(quoting_arg())
+                .arg(no_group_arg())
+                .arg(profile_arg()),
+        )
+        .get_matches();
+
+    let procfs = match matches.value_of("proc-root") {
+        Some(root) => ProcFs::new(root),
+        None => ProcFs::default(),
+    };
+
+    let result = if let Some(m) = matches.subcommand_matches("args") {
+        run_args(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("children") {
+        run_children(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("deleted") {
+        run_deleted(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("env") {
+        run_env(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("fds") {
+        run_fds(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("maps") {
+        run_maps(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("port") {
+        run_port(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("prettify") {
+        run_prettify(m)
+    } else if let Some(m) = matches.subcommand_matches("repro") {
+        run_repro(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("stale") {
+        run_stale(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("tree") {
+        run_tree(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("whatps") {
+        run_whatps(&procfs, m)
+    } else if let Some(m) = matches.subcommand_matches("who-has") {
+        run_who_has(&procfs, m)
+    } else {
+        Ok(())
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}: {}", env!("CARGO_PKG_NAME"), e);
+        process::exit(e.exit_code());
+    }
+}
+
+/// A process as JSON, with its parents under `ancestors`
+fn ancestry_json(procfs: &ProcFs, pid: u32) -> Result<Json, MupsError> {
+    let mut ancestors = procfs.ancestors(pid)?;
+    ancestors.reverse();
+
+    let mut chain = Vec::new();
+
+    for pid in ancestors {
+        chain.push(process_json(procfs, pid)?);
+    }
+
+    let record = chain.pop().unwrap_or_else(Json::object);
+    Ok(record.field("ancestors", chain))
+}
+
+fn diff_arg<'a, 'b>(help: &'b str) -> Arg<'a, 'b> {
+    Arg::with_name("diff")
+        .long("diff")
+        .help(help)
+        .value_names(&["PID_A", "PID_B"])
+        .takes_value(true)
+        .number_of_values(2)
+}
+
+/// The two processes to compare, if --diff was given
+///
+/// clap would extend conflicts of --diff to the whole selector group
+/// it belongs to, so they are checked here instead.
+fn diff_pids(matches: &ArgMatches, others: &[&str]) -> Option<Vec<u32>> {
+    if !matches.is_present("diff") {
+        return None;
+    }
+
+    let selectors = [
+        "pid",
+        "name",
+        "cmdline-regex",
+        "user",
+        "exe",
+        "parent",
+        "newest",
+        "oldest",
+    ];
+    if let Some(other) = selectors
+        .iter()
+        .chain(others)
+        .find(|a| matches.is_present(a))
+    {
+        clap::Error::with_description(
+            &format!("--diff can not be used with --{}", other),
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+
+    Some(values_t!(matches, "diff", u32).unwrap_or_else(|e| e.exit()))
+}
+
+fn format_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("format")
+        .long("format")
+        .help("output format")
+        .value_name("FORMAT")
+        .takes_value(true)
+        .possible_values(&["text", "json", "ndjson"])
+        .default_value("text")
+}
+
+fn output_format(matches: &ArgMatches) -> Format {
+    match matches.value_of("format") {
+        Some("json") => Format::Json,
+        Some("ndjson") => Format::Ndjson,
+        _ => Format::Text,
+    }
+}
+
+fn output_arg_format(matches: &ArgMatches) -> ArgFormat {
+    let quoting = match matches.value_of("quoting") {
+        Some("none") => Quoting::None,
+        Some("bash") => Quoting::Bash,
+        _ => Quoting::Posix,
+    };
+
+    ArgFormat {
+        quoting,
+        grouping: !matches.is_present("no-group"),
+        profile: selected_profile(matches),
+        expand_response_files: matches.is_present("expand-response-files"),
+    }
+}
+
+/// Print a process and its parents, from the top down
+fn print_ancestry(procfs: &ProcFs, pid: u32, format: &ArgFormat) -> Result<(), MupsError> {
+    let mut ancestors = procfs.ancestors(pid)?;
+    ancestors.reverse();
+
+    for pid in ancestors {
+        print_process(procfs, pid, format)?;
+    }
+
+    Ok(())
+}
+
+/// Print records as one JSON array, or as one JSON object per line
+fn print_json(format: Format, records: Vec<Json>) -> Result<(), MupsError> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    if format == Format::Ndjson {
+        for record in records {
+            writeln!(out, "{}", record)?;
+        }
+    } else {
+        writeln!(out, "{}", Json::Array(records).pretty())?;
+    }
+
+    Ok(())
+}
+
+fn print_process(procfs: &ProcFs, pid: u32, format: &ArgFormat) -> Result<(), MupsError> {
+    let state = match ProcStat::read_pid(procfs, pid) {
+        Ok(stat) => stat.state,
+        Err(_) => '?',
+    };
+
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    writeln!(out, "\npid {} [{}]:", pid, state)?;
+
+    match procfs.write_cmdline(&mut out, pid, format) {
+        Ok(()) => Ok(()),
+        Err(MupsError::NoSuchProcess(_)) => {
+            writeln!(out, "(process has exited)")?;
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn print_threads(procfs: &ProcFs, pid: u32) -> Result<(), MupsError> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    for stat in read_threads(procfs, pid)? {
+        writeln!(out, "thread {} [{}]: {}", stat.pid, stat.state, stat.comm)?;
+    }
+
+    Ok(())
+}
+
+fn no_group_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("no-group")
+        .long("no-group")
+        .help("put every argument on its own line, even option values")
+}
+
+fn profile_arg<'a, 'b>() -> Arg<'a, 'b> {
+    let mut names = Profile::names();
+    names.push("auto");
+
+    Arg::with_name("profile")
+        .long("profile")
+        .help("command line syntax to assume, instead of guessing it from the program name")
+        .value_name("PROFILE")
+        .takes_value(true)
+        .possible_values(&names)
+        .default_value("auto")
+}
+
+fn quoting_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("quoting")
+        .long("quoting")
+        .help("how to quote arguments for a shell")
+        .value_name("MODE")
+        .takes_value(true)
+        .possible_values(&["posix", "bash", "none"])
+        .default_value("posix")
+}
+
+/// Threads of a process, other than the main thread
+fn read_threads(procfs: &ProcFs, pid: u32) -> Result<Vec<ProcStat>, MupsError> {
+    let tids = match procfs.tids(pid) {
+        Ok(tids) => tids,
+        Err(MupsError::NoSuchProcess(_)) => {
+            return Ok(Vec::new());
+        }
+        Err(e) => {
+            return Err(e);
+        }
+    };
+
+    let mut threads = Vec::new();
+
+    for tid in tids.into_iter().filter(|t| *t != pid) {
+        match ProcStat::read_tid(procfs, pid, tid) {
+            Ok(stat) => threads.push(stat),
+            Err(MupsError::NoSuchProcess(_)) => {}
+            Err(e) => {
+                return Err(e);
             }
-        };
+        }
+    }
 
-        let (comm, stat_end) = {
-            let lparen = stat.find('(').unwrap();
-            let rparen = stat.rfind(')').unwrap();
+    Ok(threads)
+}
 
-            (&stat[(lparen + 1)..rparen], &stat[(rparen + 2)..])
-        };
+fn run_args(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    if let Some(pids) = diff_pids(matches, &[]) {
+        return run_args_diff(procfs, matches, &pids);
+    }
+
+    let pids = select_pids(procfs, matches)?;
+    let format = output_format(matches);
+    let arg_format = output_arg_format(matches);
+
+    if format != Format::Text {
+        let mut records = Vec::new();
 
-        let mut pieces = stat_end.split(' ');
+        for pid in pids {
+            let mut record = process_json(procfs, pid)?;
 
-        let state = match pieces.next() {
-            Some(s) => s.chars().next().unwrap(),
-            None => {
-                return None;
+            if arg_format.expand_response_files {
+                if let Ok(args) = procfs.read_cmdline(pid) {
+                    let expanded = expand_response_files(procfs, pid, &args, arg_format.profile);
+                    record = record.field("response_files", expanded.files_json());
+                }
             }
-        };
 
-        let ppid = pieces.next().unwrap().parse::<u32>().unwrap();
+            records.push(record);
+        }
+
+        print_json(format, records)?;
+        return Ok(());
+    }
 
-        Some(ProcStat {
-            comm: String::from(comm),
-            pid: pid,
-            ppid: ppid,
-            state: state,
-        })
+    if pids.len() == 1 {
+        return procfs.cmdline_to_stdout(pids[0], &arg_format);
     }
+
+    for pid in pids {
+        print_process(procfs, pid, &arg_format)?;
+    }
+
+    Ok(())
 }
 
-fn bytes_from_path<P: AsRef<Path>>(path: P) -> Option<Vec<u8>> {
-    use std::io::Read;
+fn run_args_diff(procfs: &ProcFs, matches: &ArgMatches, pids: &[u32]) -> Result<(), MupsError> {
+    let arg_format = output_arg_format(matches);
+    let mut argvs = Vec::new();
 
-    let mut file = match File::open(path) {
-        Ok(file) => file,
-        Err(_) => {
-            return None;
+    for &pid in pids {
+        let args = procfs.read_cmdline(pid)?;
+
+        if arg_format.expand_response_files {
+            argvs.push(expand_response_files(procfs, pid, &args, arg_format.profile).args);
+        } else {
+            argvs.push(args);
         }
-    };
+    }
 
-    let mut buf = Vec::new();
+    match output_format(matches) {
+        Format::Text => {
+            stdout().write_all(&format_args_diff(&argvs[0], &argvs[1], &arg_format))?;
+        }
+        format => {
+            let changes: Vec<Json> = diff(&argvs[0], &argvs[1])
+                .into_iter()
+                .map(|edit| {
+                    let (op, arg) = match edit {
+                        Edit::Same(n, _) => ("same", &argvs[0][n]),
+                        Edit::Removed(n) => ("removed", &argvs[0][n]),
+                        Edit::Added(n) => ("added", &argvs[1][n]),
+                    };
+                    bytes_field(Json::object().field("op", op), "arg", arg)
+                })
+                .collect();
 
-    if file.read_to_end(&mut buf).is_err() {
-        return None;
+            let record = Json::object()
+                .field("pid_a", pids[0])
+                .field("pid_b", pids[1])
+                .field("changes", changes);
+            print_json(format, vec![record])?;
+        }
     }
 
-    Some(buf)
+    Ok(())
 }
 
-fn cmdline_to_stdout(pid: u32) {
-    /* It is a bit ugly to couple two distinct I/O actions like this
-     * but this lets us neatly bypass decoding and encoding stuff. */
-    use std::io::Write;
+fn run_children(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let pid = value_t!(matches, "pid", u32).unwrap_or_else(|e| e.exit());
+    let threads = matches.is_present("threads");
+    let format = output_format(matches);
+    let arg_format = output_arg_format(matches);
+
+    let tree = ProcessTree::read(procfs)?;
+
+    if tree.get(pid).is_none() {
+        return Err(MupsError::NoSuchProcess(pid));
+    }
+
+    let mut records = Vec::new();
+
+    for pid in tree.descendants(pid) {
+        if format == Format::Text {
+            print_process(procfs, pid, &arg_format)?;
+
+            if threads {
+                print_threads(procfs, pid)?;
+            }
+            continue;
+        }
 
-    let cmdline = {
-        let cmdline_path = format!("/proc/{}/cmdline", pid);
-        bytes_from_path(&cmdline_path).expect("requested process has to exist")
+        let mut record = process_json(procfs, pid)?;
+
+        if threads {
+            let thread_records: Vec<Json> = read_threads(procfs, pid)?
+                .into_iter()
+                .map(|t| {
+                    Json::object()
+                        .field("tid", t.pid)
+                        .field("state", t.state)
+                        .field("comm", t.comm)
+                })
+                .collect();
+            record = record.field("threads", thread_records);
+        }
+
+        records.push(record);
+    }
+
+    if format != Format::Text {
+        print_json(format, records)?;
+    }
+
+    Ok(())
+}
+
+fn run_deleted(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    let mut files = find_deleted(procfs)?;
+    files.sort_by_key(|f| Reverse(f.size));
+
+    let comm = |pid: u32| match ProcStat::read_pid(procfs, pid) {
+        Ok(stat) => stat.comm,
+        Err(_) => String::from("?"),
+    };
+    let mount = |file: &DeletedFile| match file.mount {
+        Some(ref mount) => mount.to_string_lossy().into_owned(),
+        None => String::from("?"),
     };
 
-    let mut pieces = cmdline[..cmdline.len() - 1].split(|b| *b == 0);
+    let format = output_format(matches);
+
+    if format != Format::Text {
+        let filesystems: Vec<Json> = usage_by_device(&files)
+            .into_iter()
+            .map(|(dev, usage)| {
+                let file = files.iter().find(|f| f.dev == dev).unwrap();
+                Json::object()
+                    .field("device", device_name(dev))
+                    .field(
+                        "mount",
+                        file.mount
+                            .as_ref()
+                            .map(|m| m.to_string_lossy().into_owned()),
+                    )
+                    .field("size", usage.size as i64)
+                    .field("files", usage.files as i64)
+            })
+            .collect();
 
-    let first = pieces.next().expect("need to have some argument");
-    let separator: &[u8] = " \\\n    ".as_bytes();
+        let processes: Vec<Json> = usage_by_pid(&files)
+            .into_iter()
+            .map(|(pid, usage)| {
+                let held: Vec<Json> = files
+                    .iter()
+                    .filter(|f| f.pid == pid)
+                    .map(|f| {
+                        Json::object()
+                            .field("fd", f.fd)
+                            .field("path", f.path.to_string_lossy().into_owned())
+                            .field("size", f.size as i64)
+                            .field("device", device_name(f.dev))
+                            .field(
+                                "mount",
+                                f.mount.as_ref().map(|m| m.to_string_lossy().into_owned()),
+                            )
+                    })
+                    .collect();
+
+                Json::object()
+                    .field("pid", pid)
+                    .field("comm", comm(pid))
+                    .field("size", usage.size as i64)
+                    .field("files", held)
+            })
+            .collect();
+
+        let record = Json::object()
+            .field("filesystems", filesystems)
+            .field("processes", processes);
+        print_json(format, vec![record])?;
+        return Ok(());
+    }
+
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "{:>6} {:>5} FILESYSTEM", "SIZE", "FILES")?;
+    for (dev, usage) in usage_by_device(&files) {
+        let file = files.iter().find(|f| f.dev == dev).unwrap();
+        writeln!(
+            out,
+            "{:>6} {:>5} {} ({})",
+            format_size(usage.size),
+            usage.files,
+            mount(file),
+            device_name(dev)
+        )?;
+    }
+
+    writeln!(out, "\n{:>6} {:>5} {:>7} COMM", "SIZE", "FILES", "PID")?;
+    for (pid, usage) in usage_by_pid(&files) {
+        writeln!(
+            out,
+            "{:>6} {:>5} {:>7} {}",
+            format_size(usage.size),
+            usage.files,
+            pid,
+            comm(pid)
+        )?;
+    }
+
+    writeln!(out, "\n{:>6} {:>7} {:>4} PATH", "SIZE", "PID", "FD")?;
+    for file in &files {
+        writeln!(
+            out,
+            "{:>6} {:>7} {:>4} {}",
+            format_size(file.size),
+            file.pid,
+            file.fd,
+            file.path.display()
+        )?;
+    }
+
+    Ok(())
+}
 
+fn run_env(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
     let stdout = stdout();
-    let mut out_lock = stdout.lock();
-    out_lock.write(first).unwrap();
+    let mut out = stdout.lock();
 
-    for piece in pieces {
-        out_lock.write(separator).unwrap();
-        out_lock.write(piece).unwrap();
+    let patterns = var_patterns(matches);
+
+    if let Some(pids) = diff_pids(matches, &["command"]) {
+        return run_env_diff(procfs, matches, &pids, &patterns);
+    }
+
+    let pids = select_pids(procfs, matches)?;
+    let format = output_format(matches);
+    let arg_format = output_arg_format(matches);
+
+    let mut records = Vec::new();
+
+    for &pid in &pids {
+        let result = Environ::read_pid(procfs, pid).and_then(|environ| {
+            let args = procfs.read_cmdline(pid)?;
+            Ok((environ, args))
+        });
+
+        let (mut environ, args) = match result {
+            Ok(r) => r,
+            Err(MupsError::NoSuchProcess(_)) if pids.len() > 1 => {
+                if format == Format::Text {
+                    writeln!(out, "\npid {}:\n(process has exited)", pid)?;
+                } else {
+                    records.push(Json::object().field("pid", pid).field("exited", true));
+                }
+                continue;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+
+        if !patterns.is_empty() {
+            environ.retain_names(&patterns);
+        }
+        if matches.is_present("sort") {
+            environ.sort();
+        }
+
+        if format != Format::Text {
+            let record = Json::object().field("pid", pid);
+            records.push(bytes_fields(record, "environ", environ.entries()));
+            continue;
+        }
+
+        if pids.len() > 1 {
+            writeln!(out, "\npid {}:", pid)?;
+        }
+
+        if matches.is_present("command") {
+            out.write_all(&format_env_command(&environ, &args, &arg_format))?;
+            out.write_all(b"\n")?;
+        } else {
+            out.write_all(&format_environ(&environ, arg_format.quoting))?;
+        }
+    }
+
+    if format != Format::Text {
+        print_json(format, records)?;
     }
-    out_lock.write(b"\n").unwrap();
+
+    Ok(())
 }
 
-#[allow(dead_code)]
-fn format_arglist(args: &[&str]) -> String {
-    args.join(" \\\n    ")
+fn run_env_diff(
+    procfs: &ProcFs,
+    matches: &ArgMatches,
+    pids: &[u32],
+    patterns: &[Regex],
+) -> Result<(), MupsError> {
+    let mut environs = Vec::new();
+
+    for &pid in pids {
+        let mut environ = Environ::read_pid(procfs, pid)?;
+        if !patterns.is_empty() {
+            environ.retain_names(patterns);
+        }
+        environs.push(environ);
+    }
+
+    let diffs = diff_environ(&environs[0], &environs[1]);
+
+    match output_format(matches) {
+        Format::Text => {
+            let quoting = output_arg_format(matches).quoting;
+            stdout().write_all(&format_environ_diff(&diffs, quoting))?;
+        }
+        format => {
+            let changes: Vec<Json> = diffs
+                .iter()
+                .map(|d| {
+                    let value = |object: Json, key: &str, v: &Option<Vec<u8>>| match *v {
+                        Some(ref v) => bytes_field(object, key, v),
+                        None => object.field(key, Json::Null),
+                    };
+                    let object = bytes_field(Json::object(), "name", &d.name);
+                    let object = value(object, "old", &d.old);
+                    value(object, "new", &d.new)
+                })
+                .collect();
+
+            let record = Json::object()
+                .field("pid_a", pids[0])
+                .field("pid_b", pids[1])
+                .field("changes", changes);
+            print_json(format, vec![record])?;
+        }
+    }
+
+    Ok(())
 }
 
-fn main() {
-    let matches = App::new(env!("CARGO_PKG_NAME"))
-        .version(env!("CARGO_PKG_VERSION"))
-        .author("Joonas Sarajärvi <muep@iki.fi>")
-        .about("A yet another process info tool")
-        .subcommand(
-            SubCommand::with_name("args")
-                .about("Print out args of a running process")
-                .arg(
-                    Arg::with_name("pid")
-                        .short("p")
-                        .help("select process by id")
-                        .value_name("PID")
-                        .required(true)
-                        .takes_value(true),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("prettify").about("Reprint an argument list for easier viewing"),
-        )
-        .subcommand(
-            SubCommand::with_name("whatps")
-                .about("Print out args and parents of a running process")
-                .arg(
-                    Arg::with_name("pid")
-                        .short("p")
-                        .help("select process by id")
-                        .value_name("PID")
-                        .required(true)
-                        .takes_value(true),
-                ),
-        )
-        .get_matches();
+fn run_fds(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    let pid = value_t!(matches, "pid", u32).unwrap_or_else(|e| e.exit());
+    let fds = read_fds(procfs, pid)?;
+
+    /* Finding the other ends means looking at every process, so only
+     * do it when there is something to look for. */
+    let all = if fds
+        .iter()
+        .any(|fd| matches!(fd.target, FdTarget::Pipe(_) | FdTarget::Socket(_)))
+    {
+        all_fds(procfs)?
+    } else {
+        Vec::new()
+    };
+    let net = if fds
+        .iter()
+        .any(|fd| matches!(fd.target, FdTarget::Socket(_)))
+    {
+        NetTables::read_pid(procfs, pid)
+    } else {
+        NetTables::default()
+    };
+    let socket = |fd: &OpenFd| match fd.target {
+        FdTarget::Socket(inode) => net.find(inode),
+        _ => None,
+    };
+
+    let comm = |pid: u32| match ProcStat::read_pid(procfs, pid) {
+        Ok(stat) => stat.comm,
+        Err(_) => String::from("?"),
+    };
 
-    if let Some(ref m) = matches.subcommand_matches("args") {
-        run_args(m);
-    } else if matches.subcommand_matches("prettify").is_some() {
-        run_prettify();
-    } else if let Some(ref m) = matches.subcommand_matches("whatps") {
-        run_whatps(m);
+    let format = output_format(matches);
+
+    if format != Format::Text {
+        let records = fds
+            .iter()
+            .map(|fd| {
+                let peers: Vec<Json> = peers(procfs, &all, &net, pid, fd)
+                    .into_iter()
+                    .map(|(p, f, mode)| {
+                        Json::object()
+                            .field("pid", p)
+                            .field("comm", comm(p))
+                            .field("fd", f)
+                            .field("mode", mode)
+                    })
+                    .collect();
+
+                let mut record = Json::object()
+                    .field("fd", fd.fd)
+                    .field("type", fd.target.kind())
+                    .field("target", fd.target.to_string());
+                if let Some(ref info) = fd.info {
+                    record = record
+                        .field("mode", info.mode())
+                        .field("flags", info.flag_names())
+                        .field("pos", info.pos as i64);
+                }
+                if let Some(socket) = socket(fd) {
+                    record = record.field("socket", socket_json(&socket));
+                }
+                record.field("peers", peers)
+            })
+            .collect();
+
+        print_json(format, records)?;
+        return Ok(());
     }
+
+    let flags: Vec<String> = fds
+        .iter()
+        .map(|fd| match fd.info {
+            Some(ref info) => info.flag_names().join(","),
+            None => String::from("?"),
+        })
+        .collect();
+    let width = flags.iter().map(|f| f.len()).max().unwrap_or(0).max(5);
+
+    writeln!(
+        out,
+        "{:>4} {:<4} {:>10} {:<width$} TARGET",
+        "FD",
+        "MODE",
+        "POS",
+        "FLAGS",
+        width = width
+    )?;
+
+    for (fd, flags) in fds.iter().zip(flags) {
+        let (mode, pos) = match fd.info {
+            Some(ref info) => (info.mode(), info.pos.to_string()),
+            None => ("?", String::from("?")),
+        };
+
+        let mut line = format!(
+            "{:>4} {:<4} {:>10} {:<width$} {}",
+            fd.fd,
+            mode,
+            pos,
+            flags,
+            fd.target,
+            width = width
+        );
+
+        if let Some(socket) = socket(fd) {
+            line.push_str(&format!(" {}", socket));
+        }
+
+        for (p, f, mode) in peers(procfs, &all, &net, pid, fd) {
+            line.push_str(&format!(" <-> {} ({}) fd {} {}", p, comm(p), f, mode));
+        }
+
+        writeln!(out, "{}", line)?;
+    }
+
+    Ok(())
 }
 
-fn prettify(stuff_in: &str) -> String {
-    let pieces: Vec<&str> = stuff_in.split(' ').collect();
+fn run_maps(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    let pid = value_t!(matches, "pid", u32).unwrap_or_else(|e| e.exit());
+    let format = output_format(matches);
+
+    if matches.is_present("rollup") {
+        let usage = read_smaps_rollup(procfs, pid)?;
+
+        if format != Format::Text {
+            let record = Json::object()
+                .field("rss", usage.rss as i64)
+                .field("pss", usage.pss as i64)
+                .field("swap", usage.swap as i64);
+            print_json(format, vec![record])?;
+            return Ok(());
+        }
+
+        writeln!(out, "{:>6} {:>6} {:>6}", "RSS", "PSS", "SWAP")?;
+        writeln!(
+            out,
+            "{:>6} {:>6} {:>6}",
+            format_size(usage.rss),
+            format_size(usage.pss),
+            format_size(usage.swap)
+        )?;
+
+        return Ok(());
+    }
+
+    let regions = if matches.is_present("smaps") {
+        read_smaps(procfs, pid)?
+    } else {
+        read_maps(procfs, pid)?
+    };
+
+    let usage_fields = |record: Json, usage: Option<MemUsage>| match usage {
+        Some(usage) => record
+            .field("rss", usage.rss as i64)
+            .field("pss", usage.pss as i64)
+            .field("swap", usage.swap as i64),
+        None => record,
+    };
+    let usage_columns = |usage: Option<MemUsage>| match usage {
+        Some(usage) => format!(
+            " {:>6} {:>6} {:>6}",
+            format_size(usage.rss),
+            format_size(usage.pss),
+            format_size(usage.swap)
+        ),
+        None => String::new(),
+    };
+    let usage_header = if matches.is_present("smaps") {
+        format!(" {:>6} {:>6} {:>6}", "RSS", "PSS", "SWAP")
+    } else {
+        String::new()
+    };
+
+    if matches.is_present("regions") {
+        if format != Format::Text {
+            let records = regions
+                .iter()
+                .map(|region| {
+                    let record = Json::object()
+                        .field("start", format!("{:x}", region.start))
+                        .field("end", format!("{:x}", region.end))
+                        .field("perms", region.perms.as_str())
+                        .field("offset", region.offset as i64)
+                        .field("dev", region.dev.as_str())
+                        .field("inode", region.inode as i64)
+                        .field("kind", region.kind().name())
+                        .field(
+                            "path",
+                            region
+                                .path
+                                .as_ref()
+                                .map(|p| p.to_string_lossy().into_owned()),
+                        )
+                        .field("size", region.size() as i64);
+                    usage_fields(record, region.usage)
+                })
+                .collect();
+
+            print_json(format, records)?;
+            return Ok(());
+        }
+
+        let addresses: Vec<String> = regions
+            .iter()
+            .map(|r| format!("{:x}-{:x}", r.start, r.end))
+            .collect();
+        let width = addresses.iter().map(|a| a.len()).max().unwrap_or(0);
+
+        writeln!(
+            out,
+            "{:<width$} PERM {:>6}{} MAPPING",
+            "ADDRESS",
+            "SIZE",
+            usage_header,
+            width = width
+        )?;
+        for (region, address) in regions.iter().zip(addresses) {
+            writeln!(
+                out,
+                "{:<width$} {} {:>6}{} {}",
+                address,
+                region.perms,
+                format_size(region.size()),
+                usage_columns(region.usage),
+                region.label(),
+                width = width
+            )?;
+        }
+
+        return Ok(());
+    }
+
+    let groups = group_regions(&regions);
 
-    pieces.join(" \\\n    ")
+    if format != Format::Text {
+        let records = groups
+            .iter()
+            .map(|group| {
+                let record = Json::object()
+                    .field("mapping", group.label.as_str())
+                    .field("kind", group.kind.name())
+                    .field("regions", group.regions as u32)
+                    .field("size", group.size as i64);
+                usage_fields(record, group.usage)
+            })
+            .collect();
+
+        print_json(format, records)?;
+        return Ok(());
+    }
+
+    let total_size: u64 = groups.iter().map(|g| g.size).sum();
+    let total_usage = groups
+        .iter()
+        .try_fold(MemUsage::default(), |mut total, group| {
+            total.add(&group.usage?);
+            Some(total)
+        });
+
+    writeln!(out, "{:>6}{} MAPPING", "SIZE", usage_header)?;
+    for group in &groups {
+        writeln!(
+            out,
+            "{:>6}{} {}",
+            format_size(group.size),
+            usage_columns(group.usage),
+            group.label
+        )?;
+    }
+    writeln!(
+        out,
+        "{:>6}{} total",
+        format_size(total_size),
+        usage_columns(total_usage)
+    )?;
+
+    Ok(())
 }
 
-fn run_args(matches: &ArgMatches) {
-    let pid = matches
-        .value_of("pid")
-        .unwrap()
-        .parse::<u32>()
-        .expect("PID has to be an integer");
+fn run_port(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    let query = match matches.value_of_os("unix") {
+        Some(path) => SocketQuery::Unix(PathBuf::from(path)),
+        None => {
+            let port = value_t!(matches, "port", u16).unwrap_or_else(|e| e.exit());
+            if matches.is_present("udp") {
+                SocketQuery::Port(Protocol::Udp, port)
+            } else {
+                SocketQuery::Port(Protocol::Tcp, port)
+            }
+        }
+    };
+
+    let owners = socket_owners(procfs, &all_fds(procfs)?, &query);
+    if owners.is_empty() {
+        return Err(MupsError::NoMatchingProcess);
+    }
+
+    let mut pids: Vec<u32> = owners.iter().map(|o| o.pid).collect();
+    pids.dedup();
 
-    cmdline_to_stdout(pid);
+    let format = output_format(matches);
+
+    if format != Format::Text {
+        let mut records = Vec::new();
+
+        for &pid in &pids {
+            let sockets: Vec<Json> = owners
+                .iter()
+                .filter(|o| o.pid == pid)
+                .map(|o| {
+                    Json::object()
+                        .field("fd", o.fd)
+                        .field("socket", socket_json(&o.socket))
+                })
+                .collect();
+            records.push(ancestry_json(procfs, pid)?.field("sockets", sockets));
+        }
+
+        print_json(format, records)?;
+        return Ok(());
+    }
+
+    for owner in &owners {
+        writeln!(out, "pid {} fd {}: {}", owner.pid, owner.fd, owner.socket)?;
+    }
+
+    let arg_format = output_arg_format(matches);
+    for pid in pids {
+        print_ancestry(procfs, pid, &arg_format)?;
+    }
+
+    Ok(())
 }
 
-fn run_prettify() {
+fn run_prettify(matches: &ArgMatches) -> Result<(), MupsError> {
     use std::io::Read;
 
     let stdin_all = {
         let mut buf = String::new();
-        stdin().read_to_string(&mut buf).unwrap();
+        stdin().read_to_string(&mut buf)?;
         buf
     };
 
-    println!("{}", prettify(&stdin_all))
+    let format = ArgFormat {
+        quoting: Quoting::Bash,
+        grouping: !matches.is_present("no-group"),
+        profile: selected_profile(matches),
+        expand_response_files: false,
+    };
+
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    if matches.is_present("diff") {
+        let commands = split_commands(&stdin_all)?;
+
+        if commands.len() != 2 {
+            return Err(MupsError::BadInput(format!(
+                "expected two command lines to compare, got {}",
+                commands.len()
+            )));
+        }
+
+        out.write_all(&format_args_diff(&commands[0], &commands[1], &format))?;
+        return Ok(());
+    }
+
+    writeln!(out, "{}", prettify(&stdin_all, &format)?)?;
+
+    Ok(())
+}
+
+fn run_repro(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let pid = value_t!(matches, "pid", u32).unwrap_or_else(|e| e.exit());
+    let quoting = output_arg_format(matches).quoting;
+
+    stdout().write_all(&repro_script(procfs, pid, quoting)?)?;
+
+    Ok(())
 }
 
-fn run_whatps(matches: &ArgMatches) {
-    let mut pid = matches
-        .value_of("pid")
-        .unwrap()
-        .parse::<u32>()
-        .expect("PID has to be an integer");
+fn run_stale(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    let stale = find_stale(procfs)?;
+
+    let stat = |pid: u32| ProcStat::read_pid(procfs, pid).ok();
+    let comm = |pid: u32| stat(pid).map_or_else(|| String::from("?"), |s| s.comm);
+
+    /* What to restart: the service if there is one, or else the
+     * parent which would start the process again */
+    let groups: Vec<String> = stale
+        .iter()
+        .map(|process| match service_of(procfs, process.pid) {
+            Some(service) => service,
+            None => match stat(process.pid) {
+                Some(s) => format!("parent {} ({})", s.ppid, comm(s.ppid)),
+                None => String::from("parent ?"),
+            },
+        })
+        .collect();
+
+    let format = output_format(matches);
+
+    if format != Format::Text {
+        let records = stale
+            .iter()
+            .zip(&groups)
+            .map(|(process, group)| {
+                let files: Vec<Json> = process
+                    .files
+                    .iter()
+                    .map(|file| {
+                        Json::object()
+                            .field("path", file.path.to_string_lossy().into_owned())
+                            .field("exe", file.exe)
+                            .field("staleness", file.staleness.to_string())
+                    })
+                    .collect();
+
+                Json::object()
+                    .field("pid", process.pid)
+                    .field("comm", comm(process.pid))
+                    .field("group", group.as_str())
+                    .field("files", files)
+            })
+            .collect();
+
+        print_json(format, records)?;
+        return Ok(());
+    }
+
+    let mut order: Vec<&String> = Vec::new();
+    for group in &groups {
+        if !order.contains(&group) {
+            order.push(group);
+        }
+    }
 
-    let mut pids = vec![pid];
+    for (n, group) in order.into_iter().enumerate() {
+        if n > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{}:", group)?;
 
-    while pid != 1 {
-        let stat = match ProcStat::read_pid(pid) {
-            Some(s) => s,
-            None => {
-                break;
+        for (process, _) in stale.iter().zip(&groups).filter(|p| p.1 == group) {
+            for file in &process.files {
+                writeln!(
+                    out,
+                    "  pid {} ({}): {}{} ({})",
+                    process.pid,
+                    comm(process.pid),
+                    if file.exe { "exe " } else { "" },
+                    file.path.display(),
+                    file.staleness
+                )?;
             }
-        };
+        }
+    }
 
-        pid = stat.ppid;
-        pids.push(pid);
+    Ok(())
+}
+
+fn run_tree(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    let root = if matches.is_present("pid") {
+        value_t!(matches, "pid", u32).unwrap_or_else(|e| e.exit())
+    } else {
+        1
+    };
+
+    let options = TreeOptions {
+        ascii: matches.is_present("ascii"),
+        collapse: matches.is_present("collapse"),
+        max_depth: if matches.is_present("depth") {
+            Some(value_t!(matches, "depth", usize).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        },
+        show_args: matches.is_present("args"),
+        arg_format: output_arg_format(matches),
+    };
+
+    let tree = ProcessTree::read(procfs)?;
+
+    match output_format(matches) {
+        Format::Text => write!(out, "{}", tree.render(procfs, root, &options)?)?,
+        format => print_json(format, vec![tree.to_json(procfs, root, options.max_depth)?])?,
     }
 
-    for pid in pids.iter().rev() {
-        let state = match ProcStat::read_pid(*pid) {
-            Some(stat) => stat.state,
-            None => '?',
-        };
+    Ok(())
+}
 
-        println!("\npid {} [{}]:", pid, state);
+fn run_whatps(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let format = output_format(matches);
+    let arg_format = output_arg_format(matches);
+    let mut records = Vec::new();
 
-        cmdline_to_stdout(*pid);
+    for pid in select_pids(procfs, matches)? {
+        if format == Format::Text {
+            print_ancestry(procfs, pid, &arg_format)?;
+        } else {
+            records.push(ancestry_json(procfs, pid)?);
+        }
     }
+
+    if format != Format::Text {
+        print_json(format, records)?;
+    }
+
+    Ok(())
 }
 
-fn string_from_path<P: AsRef<Path>>(path: P) -> Option<String> {
-    use std::io::Read;
+fn run_who_has(procfs: &ProcFs, matches: &ArgMatches) -> Result<(), MupsError> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+
+    let file = PathBuf::from(matches.value_of_os("file").unwrap());
+
+    /* Processes see resolved paths, but the file may also be gone or
+     * exist only inside another mount namespace */
+    let path = match fs::canonicalize(&file) {
+        Ok(path) => path,
+        Err(_) => env::current_dir()?.join(file),
+    };
+
+    let holders = find_holders(procfs, &path)?;
+    if holders.is_empty() {
+        return Err(MupsError::NoMatchingProcess);
+    }
 
-    let mut file = match File::open(path) {
-        Ok(file) => file,
-        Err(_) => {
-            return None;
+    let mut pids: Vec<u32> = holders.iter().map(|h| h.pid).collect();
+    pids.dedup();
+
+    let format = output_format(matches);
+
+    if format != Format::Text {
+        let mut records = Vec::new();
+
+        for &pid in &pids {
+            let access: Vec<Json> = holders
+                .iter()
+                .filter(|h| h.pid == pid)
+                .map(|h| {
+                    let mut record = Json::object().field("kind", h.access.kind());
+                    if let Access::Fd(fd, mode) = h.access {
+                        record = record.field("fd", fd).field("mode", mode);
+                    }
+                    record
+                        .field("path", h.path.to_string_lossy().into_owned())
+                        .field("deleted", h.deleted)
+                })
+                .collect();
+            records.push(ancestry_json(procfs, pid)?.field("access", access));
         }
+
+        print_json(format, records)?;
+        return Ok(());
+    }
+
+    for holder in &holders {
+        writeln!(
+            out,
+            "pid {} {}: {}{}",
+            holder.pid,
+            holder.access,
+            holder.path.display(),
+            if holder.deleted { " (deleted)" } else { "" }
+        )?;
+    }
+
+    let arg_format = output_arg_format(matches);
+    for pid in pids {
+        print_ancestry(procfs, pid, &arg_format)?;
+    }
+
+    Ok(())
+}
+
+/// Resolve the process selection options of a subcommand
+///
+/// When more than one process matches, the matches are listed on
+/// stderr so that an ambiguous selection does not go unnoticed.
+fn select_pids(procfs: &ProcFs, matches: &ArgMatches) -> Result<Vec<u32>, MupsError> {
+    let mut selector = Selector::default();
+
+    if matches.is_present("pid") {
+        selector.pids = values_t!(matches, "pid", u32).unwrap_or_else(|e| e.exit());
+    }
+
+    /* Our own arguments would match any --cmdline-regex, but that
+     * only matters when looking at our own /proc. */
+    if *procfs == ProcFs::default() {
+        selector.exclude.push(process::id());
+    }
+
+    selector.name = matches.value_of("name").map(String::from);
+
+    if let Some(pattern) = matches.value_of("cmdline-regex") {
+        selector.cmdline = Some(Regex::new(pattern).unwrap_or_else(|e| {
+            clap::Error::with_description(&e.to_string(), clap::ErrorKind::InvalidValue).exit()
+        }));
+    }
+
+    if let Some(user) = matches.value_of("user") {
+        selector.uid = match lookup_uid(user)? {
+            Some(uid) => Some(uid),
+            None => clap::Error::with_description(
+                &format!("no such user: {}", user),
+                clap::ErrorKind::InvalidValue,
+            )
+            .exit(),
+        };
+    }
+
+    selector.exe = matches.value_of("exe").map(PathBuf::from);
+
+    if matches.is_present("parent") {
+        selector.parent = Some(value_t!(matches, "parent", u32).unwrap_or_else(|e| e.exit()));
+    }
+
+    selector.pick = if matches.is_present("newest") {
+        Pick::Newest
+    } else if matches.is_present("oldest") {
+        Pick::Oldest
+    } else {
+        Pick::All
     };
 
-    let mut buf = String::new();
+    let pids = selector.select(procfs)?;
 
-    if file.read_to_string(&mut buf).is_err() {
-        return None;
+    if pids.is_empty() {
+        return Err(MupsError::NoMatchingProcess);
     }
 
-    Some(buf)
+    if pids.len() > 1 && selector.pids.len() != pids.len() {
+        let pid_list: Vec<String> = pids.iter().map(|p| p.to_string()).collect();
+        eprintln!(
+            "{}: {} processes match: {}",
+            env!("CARGO_PKG_NAME"),
+            pids.len(),
+            pid_list.join(" ")
+        );
+    }
+
+    Ok(pids)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn selected_profile(matches: &ArgMatches) -> Option<Profile> {
+    matches.value_of("profile").and_then(Profile::from_name)
+}
 
-    #[test]
-    fn test_format_arglist_1() {
-        let args = ["gcc", "-c", "hello.c"];
+fn selector_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("pid")
+            .short("p")
+            .help("select process by id")
+            .value_name("PID")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("name")
+            .long("name")
+            .help("select processes by name")
+            .value_name("NAME")
+            .takes_value(true),
+        Arg::with_name("cmdline-regex")
+            .long("cmdline-regex")
+            .help("select processes whose arguments match a regular expression")
+            .value_name("REGEX")
+            .takes_value(true),
+        Arg::with_name("user")
+            .long("user")
+            .help("select processes by user name or id")
+            .value_name("USER")
+            .takes_value(true),
+        Arg::with_name("exe")
+            .long("exe")
+            .help("select processes by executable path or file name")
+            .value_name("EXE")
+            .takes_value(true),
+        Arg::with_name("parent")
+            .long("parent")
+            .help("select children of a process")
+            .value_name("PPID")
+            .takes_value(true),
+        Arg::with_name("newest")
+            .long("newest")
+            .help("select only the most recently started of the matches")
+            .conflicts_with("oldest"),
+        Arg::with_name("oldest")
+            .long("oldest")
+            .help("select only the least recently started of the matches"),
+    ]
+}
 
-        let expected_pretty = include_str!("td/format_arglist_1.txt");
-        let prettified = format_arglist(&args);
+/// Patterns of the variable names to show, from the --var globs
+fn var_patterns(matches: &ArgMatches) -> Vec<Regex> {
+    let mut patterns = Vec::new();
 
-        assert_eq!(expected_pretty, &prettified);
+    if matches.is_present("var") {
+        for glob in values_t!(matches, "var", String).unwrap_or_else(|e| e.exit()) {
+            patterns.push(Regex::from_glob(&glob).unwrap_or_else(|e| {
+                clap::Error::with_description(&e.to_string(), clap::ErrorKind::InvalidValue).exit()
+            }));
+        }
     }
 
-    #[test]
-    fn test_prettify_1() {
-        let text = include_str!("td/prettify_1_orig.txt");
-        let expected_pretty = include_str!("td/prettify_1_pretty.txt");
+    patterns
+}
 
-        let prettified = prettify(text);
+fn selector_group() -> ArgGroup<'static> {
+    ArgGroup::with_name("selector")
+        .args(&["pid", "name", "cmdline-regex", "user", "exe", "parent"])
+        .required(true)
+        .multiple(true)
+}
 
-        assert_eq!(expected_pretty, &prettified);
+fn socket_json(socket: &SocketInfo) -> Json {
+    match *socket {
+        SocketInfo::Inet(ref socket) => Json::object()
+            .field("protocol", socket.protocol.name())
+            .field("local", socket.local.to_string())
+            .field("remote", socket.remote.to_string())
+            .field("state", socket.state_name()),
+        SocketInfo::Unix(ref socket) => Json::object()
+            .field("protocol", "unix")
+            .field("type", socket.type_name())
+            .field(
+                "path",
+                socket
+                    .path
+                    .as_ref()
+                    .map(|p| p.to_string_lossy().into_owned()),
+            )
+            .field("state", socket.state_name()),
     }
 }
@@ -0,0 +1,349 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use deleted::make_dev;
+use error::MupsError;
+use procfs::ProcFs;
+
+/// One line of /proc/<pid>/maps
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapRegion {
+    pub start: u64,
+    pub end: u64,
+    /// Like `r-xp`, with `p` or `s` for private or shared
+    pub perms: String,
+    pub offset: u64,
+    /// The device as `major:minor` in hexadecimal
+    pub dev: String,
+    pub inode: u64,
+    /// The backing file, or a name like `[heap]`, or nothing for
+    /// anonymous memory
+    pub path: Option<PathBuf>,
+    /// Memory use, only known when read from smaps
+    pub usage: Option<MemUsage>,
+}
+
+/// What kind of memory a region is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionKind {
+    File,
+    Heap,
+    Stack,
+    Anonymous,
+    /// Regions the kernel provides, like `[vdso]`
+    Special,
+}
+
+impl RegionKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            RegionKind::File => "file",
+            RegionKind::Heap => "heap",
+            RegionKind::Stack => "stack",
+            RegionKind::Anonymous => "anonymous",
+            RegionKind::Special => "special",
+        }
+    }
+}
+
+/// Memory use of a region from /proc/<pid>/smaps, in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemUsage {
+    pub rss: u64,
+    pub pss: u64,
+    pub swap: u64,
+}
+
+impl MemUsage {
+    pub fn add(&mut self, other: &MemUsage) {
+        self.rss += other.rss;
+        self.pss += other.pss;
+        self.swap += other.swap;
+    }
+}
+
+impl MapRegion {
+    pub fn parse(line: &[u8]) -> Option<MapRegion> {
+        let mut rest = line;
+        let mut field = || -> Option<&[u8]> {
+            let start = rest.iter().position(|&c| c != b' ')?;
+            let len = rest[start..]
+                .iter()
+                .position(|&c| c == b' ')
+                .unwrap_or(rest.len() - start);
+            let field = &rest[start..(start + len)];
+            rest = &rest[(start + len)..];
+            Some(field)
+        };
+
+        let range = String::from_utf8_lossy(field()?).into_owned();
+        let perms = String::from_utf8_lossy(field()?).into_owned();
+        let offset = String::from_utf8_lossy(field()?).into_owned();
+        let dev = String::from_utf8_lossy(field()?).into_owned();
+        let inode = String::from_utf8_lossy(field()?).into_owned();
+
+        let dash = range.find('-')?;
+
+        /* The path is the rest of the line and may contain spaces */
+        let path = rest
+            .iter()
+            .position(|&c| c != b' ')
+            .map(|start| PathBuf::from(OsStr::from_bytes(&rest[start..])));
+
+        Some(MapRegion {
+            start: u64::from_str_radix(&range[..dash], 16).ok()?,
+            end: u64::from_str_radix(&range[(dash + 1)..], 16).ok()?,
+            perms,
+            offset: u64::from_str_radix(&offset, 16).ok()?,
+            dev,
+            inode: inode.parse().ok()?,
+            path,
+            usage: None,
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// The device number of the backing file, as `stat` reports it
+    pub fn device(&self) -> Option<u64> {
+        let colon = self.dev.find(':')?;
+        let major = u64::from_str_radix(&self.dev[..colon], 16).ok()?;
+        let minor = u64::from_str_radix(&self.dev[(colon + 1)..], 16).ok()?;
+
+        Some(make_dev(major, minor))
+    }
+
+    pub fn kind(&self) -> RegionKind {
+        let path = match self.path {
+            Some(ref path) => path.as_os_str().as_bytes(),
+            None => {
+                return RegionKind::Anonymous;
+            }
+        };
+
+        if path == b"[heap]" {
+            RegionKind::Heap
+        } else if path.starts_with(b"[stack") {
+            RegionKind::Stack
+        } else if path.starts_with(b"[anon:") {
+            RegionKind::Anonymous
+        } else if path.starts_with(b"[") {
+            RegionKind::Special
+        } else {
+            RegionKind::File
+        }
+    }
+
+    /// The path or name of the region, `[anon]` for anonymous memory
+    pub fn label(&self) -> String {
+        match self.path {
+            Some(ref path) => path.to_string_lossy().into_owned(),
+            None => String::from("[anon]"),
+        }
+    }
+}
+
+/// Regions with the same backing file or name, added up
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapGroup {
+    pub label: String,
+    pub kind: RegionKind,
+    pub regions: usize,
+    pub size: u64,
+    /// Missing unless all the regions came from smaps
+    pub usage: Option<MemUsage>,
+}
+
+/// Add up the regions by backing file or name, in order of address
+pub fn group_regions(regions: &[MapRegion]) -> Vec<MapGroup> {
+    let mut groups: Vec<MapGroup> = Vec::new();
+
+    for region in regions {
+        let label = region.label();
+        let index = match groups.iter().position(|g| g.label == label) {
+            Some(index) => index,
+            None => {
+                groups.push(MapGroup {
+                    label,
+                    kind: region.kind(),
+                    regions: 0,
+                    size: 0,
+                    usage: Some(MemUsage::default()),
+                });
+                groups.len() - 1
+            }
+        };
+
+        let group = &mut groups[index];
+        group.regions += 1;
+        group.size += region.size();
+        group.usage = match (group.usage, region.usage) {
+            (Some(mut total), Some(usage)) => {
+                total.add(&usage);
+                Some(total)
+            }
+            _ => None,
+        };
+    }
+
+    groups
+}
+
+/// Read and parse /proc/<pid>/maps
+pub fn read_maps(procfs: &ProcFs, pid: u32) -> Result<Vec<MapRegion>, MupsError> {
+    let maps = procfs.read_pid_file(pid, "maps")?;
+    let mut regions = Vec::new();
+
+    for line in maps.split(|&c| c == b'\n').filter(|l| !l.is_empty()) {
+        match MapRegion::parse(line) {
+            Some(region) => regions.push(region),
+            None => {
+                return Err(MupsError::MalformedProcFile {
+                    path: procfs.pid_path(pid, "maps"),
+                    content: String::from_utf8_lossy(&maps).into_owned(),
+                });
+            }
+        }
+    }
+
+    Ok(regions)
+}
+
+/// Read and parse /proc/<pid>/smaps, which has the memory use too
+pub fn read_smaps(procfs: &ProcFs, pid: u32) -> Result<Vec<MapRegion>, MupsError> {
+    read_smaps_file(procfs, pid, "smaps")
+}
+
+/// Read the memory use of all regions together from smaps_rollup
+///
+/// The kernel sums this up itself, which is much cheaper than
+/// reading smaps for processes with many regions.
+pub fn read_smaps_rollup(procfs: &ProcFs, pid: u32) -> Result<MemUsage, MupsError> {
+    let regions = read_smaps_file(procfs, pid, "smaps_rollup")?;
+
+    Ok(regions
+        .iter()
+        .fold(MemUsage::default(), |mut total, region| {
+            if let Some(ref usage) = region.usage {
+                total.add(usage);
+            }
+            total
+        }))
+}
+
+fn read_smaps_file(procfs: &ProcFs, pid: u32, name: &str) -> Result<Vec<MapRegion>, MupsError> {
+    let smaps = procfs.read_pid_file(pid, name)?;
+
+    match parse_smaps(&smaps) {
+        Some(regions) => Ok(regions),
+        None => Err(MupsError::MalformedProcFile {
+            path: procfs.pid_path(pid, name),
+            content: String::from_utf8_lossy(&smaps).into_owned(),
+        }),
+    }
+}
+
+/// Parse smaps, or smaps_rollup which has the same layout
+///
+/// Each region is followed by lines like `Rss:    12 kB`, of which
+/// only the ones for `MemUsage` are picked up.
+pub fn parse_smaps(smaps: &[u8]) -> Option<Vec<MapRegion>> {
+    let mut regions: Vec<MapRegion> = Vec::new();
+
+    for line in smaps.split(|&c| c == b'\n').filter(|l| !l.is_empty()) {
+        if let Some(mut region) = MapRegion::parse(line) {
+            region.usage = Some(MemUsage::default());
+            regions.push(region);
+            continue;
+        }
+
+        let line = String::from_utf8_lossy(line);
+        let colon = line.find(':')?;
+        let usage = regions.last_mut()?.usage.as_mut()?;
+
+        let field = match &line[..colon] {
+            "Rss" => &mut usage.rss,
+            "Pss" => &mut usage.pss,
+            "Swap" => &mut usage.swap,
+            _ => {
+                continue;
+            }
+        };
+
+        let value = line[(colon + 1)..].trim();
+        let kb: u64 = value.trim_end_matches(" kB").parse().ok()?;
+        *field = kb * 1024;
+    }
+
+    Some(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_region_parse() {
+        let region = MapRegion::parse(
+            b"7f1c2a400000-7f1c2a428000 r--p 00001000 fd:01 1837290                    /usr/lib/my lib.so (deleted)",
+        )
+        .unwrap();
+
+        assert_eq!(0x7f1c2a400000, region.start);
+        assert_eq!(0x28000, region.size());
+        assert_eq!("r--p", region.perms);
+        assert_eq!(0x1000, region.offset);
+        assert_eq!("fd:01", region.dev);
+        assert_eq!(Some((253 << 8) | 1), region.device());
+        assert_eq!(1837290, region.inode);
+        assert_eq!(
+            Some(PathBuf::from("/usr/lib/my lib.so (deleted)")),
+            region.path
+        );
+
+        let anon = MapRegion::parse(b"7f1c2a400000-7f1c2a428000 rw-p 00000000 00:00 0 ").unwrap();
+        assert_eq!(None, anon.path);
+
+        assert_eq!(
+            None,
+            MapRegion::parse(b"7f1c2a400000 rw-p 00000000 00:00 0")
+        );
+    }
+
+    #[test]
+    fn test_parse_smaps() {
+        let regions = parse_smaps(include_bytes!("td/smaps_1.txt")).unwrap();
+
+        assert_eq!(5, regions.len());
+        assert_eq!(RegionKind::File, regions[0].kind());
+        assert_eq!(RegionKind::Heap, regions[2].kind());
+        assert_eq!(RegionKind::Anonymous, regions[3].kind());
+        assert_eq!("[anon]", regions[3].label());
+        assert_eq!(RegionKind::Stack, regions[4].kind());
+        assert_eq!(
+            Some(MemUsage {
+                rss: 1024 * 1024,
+                pss: 512 * 1024,
+                swap: 0,
+            }),
+            regions[0].usage
+        );
+
+        let groups = group_regions(&regions);
+        assert_eq!(4, groups.len());
+        assert_eq!("/usr/lib/libc.so.6", groups[0].label);
+        assert_eq!(2, groups[0].regions);
+        assert_eq!(0x200000 + 0x1000, groups[0].size);
+        assert_eq!(
+            Some(MemUsage {
+                rss: 1028 * 1024,
+                pss: 514 * 1024,
+                swap: 8 * 1024,
+            }),
+            groups[0].usage
+        );
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+
+use fd::FdTarget;
+use procfs::ProcFs;
+
+const TCP_STATES: &[&str] = &[
+    "ESTABLISHED",
+    "SYN_SENT",
+    "SYN_RECV",
+    "FIN_WAIT1",
+    "FIN_WAIT2",
+    "TIME_WAIT",
+    "CLOSE",
+    "CLOSE_WAIT",
+    "LAST_ACK",
+    "LISTEN",
+    "CLOSING",
+];
+
+/// Flag of a unix socket that accepts connections
+const UNIX_ACCEPTCON: u32 = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+/// A TCP or UDP socket from /proc/net/{tcp,tcp6,udp,udp6}
+#[derive(Clone, Debug, PartialEq)]
+pub struct InetSocket {
+    pub protocol: Protocol,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    /// The state as the kernel numbers it, see `state_name`
+    pub state: u8,
+    pub uid: u32,
+    pub inode: u64,
+}
+
+impl InetSocket {
+    pub fn state_name(&self) -> &'static str {
+        match (self.protocol, self.state) {
+            (Protocol::Udp, 1) => "ESTABLISHED",
+            (Protocol::Udp, 7) => "UNCONNECTED",
+            (Protocol::Tcp, s) if s >= 1 && (s as usize) <= TCP_STATES.len() => {
+                TCP_STATES[s as usize - 1]
+            }
+            _ => "UNKNOWN",
+        }
+    }
+
+    /// Whether the socket takes connections or datagrams on a port
+    pub fn is_listening(&self) -> bool {
+        match self.protocol {
+            Protocol::Tcp => self.state == 10,
+            Protocol::Udp => self.state == 7,
+        }
+    }
+}
+
+impl fmt::Display for InetSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.protocol.name(), self.local)?;
+        if self.remote.port() != 0 {
+            write!(f, " -> {}", self.remote)?;
+        }
+        write!(f, " {}", self.state_name())
+    }
+}
+
+/// A unix domain socket from /proc/net/unix
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnixSocket {
+    /// `SOCK_STREAM`, `SOCK_DGRAM` or `SOCK_SEQPACKET`, as numbers
+    pub socket_type: u32,
+    pub flags: u32,
+    /// The socket state, as the kernel numbers it
+    pub state: u8,
+    pub inode: u64,
+    /// The bound path, with a leading `@` for abstract names
+    pub path: Option<PathBuf>,
+}
+
+impl UnixSocket {
+    pub fn is_listening(&self) -> bool {
+        self.flags & UNIX_ACCEPTCON != 0
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.socket_type {
+            1 => "stream",
+            2 => "dgram",
+            5 => "seqpacket",
+            _ => "unknown",
+        }
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        if self.is_listening() {
+            return "LISTEN";
+        }
+        match self.state {
+            1 => "UNCONNECTED",
+            2 => "CONNECTING",
+            3 => "CONNECTED",
+            4 => "DISCONNECTING",
+            _ => "UNKNOWN",
+        }
+    }
+}
+
+impl fmt::Display for UnixSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unix {}", self.type_name())?;
+        if let Some(ref path) = self.path {
+            write!(f, " {}", path.display())?;
+        }
+        write!(f, " {}", self.state_name())
+    }
+}
+
+/// What a socket inode turned out to be
+#[derive(Clone, Debug, PartialEq)]
+pub enum SocketInfo {
+    Inet(InetSocket),
+    Unix(UnixSocket),
+}
+
+impl fmt::Display for SocketInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SocketInfo::Inet(ref socket) => socket.fmt(f),
+            SocketInfo::Unix(ref socket) => socket.fmt(f),
+        }
+    }
+}
+
+/// The sockets of a network namespace
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetTables {
+    pub inet: Vec<InetSocket>,
+    pub unix: Vec<UnixSocket>,
+}
+
+impl NetTables {
+    /// Read the sockets of the network namespace of a process
+    ///
+    /// Tables that can not be read, like tcp6 without IPv6 support,
+    /// are taken as empty.
+    pub fn read_pid(procfs: &ProcFs, pid: u32) -> NetTables {
+        let mut tables = NetTables::default();
+
+        let read = |name: &str| {
+            procfs
+                .read_pid_file(pid, &format!("net/{}", name))
+                .map(|b| String::from_utf8_lossy(&b).into_owned())
+                .unwrap_or_default()
+        };
+
+        for &(name, protocol) in &[
+            ("tcp", Protocol::Tcp),
+            ("tcp6", Protocol::Tcp),
+            ("udp", Protocol::Udp),
+            ("udp6", Protocol::Udp),
+        ] {
+            tables.inet.extend(parse_inet(&read(name), protocol));
+        }
+        tables.unix = parse_unix(&read("unix"));
+
+        tables
+    }
+
+    pub fn find(&self, inode: u64) -> Option<SocketInfo> {
+        if let Some(socket) = self.inet.iter().find(|s| s.inode == inode) {
+            return Some(SocketInfo::Inet(socket.clone()));
+        }
+        self.unix
+            .iter()
+            .find(|s| s.inode == inode)
+            .map(|s| SocketInfo::Unix(s.clone()))
+    }
+
+    /// The inode of the other end of a connection within the namespace
+    ///
+    /// Only TCP and UDP connections can be matched up this way, since
+    /// the table of unix sockets does not tell the peers.
+    pub fn peer(&self, inode: u64) -> Option<u64> {
+        let socket = self.inet.iter().find(|s| s.inode == inode)?;
+
+        if socket.remote.port() == 0 {
+            return None;
+        }
+
+        self.inet
+            .iter()
+            .find(|s| {
+                s.protocol == socket.protocol
+                    && s.local == socket.remote
+                    && s.remote == socket.local
+                    && s.inode != 0
+            })
+            .map(|s| s.inode)
+    }
+
+    /// Sockets listening on a port
+    pub fn listening(&self, protocol: Protocol, port: u16) -> Vec<&InetSocket> {
+        self.inet
+            .iter()
+            .filter(|s| s.protocol == protocol && s.local.port() == port && s.is_listening())
+            .collect()
+    }
+}
+
+/// Which sockets to look for with `socket_owners`
+#[derive(Clone, Debug, PartialEq)]
+pub enum SocketQuery {
+    /// TCP or UDP sockets with this local port
+    Port(Protocol, u16),
+    /// Unix sockets bound to this path
+    Unix(PathBuf),
+}
+
+impl SocketQuery {
+    pub fn matches(&self, socket: &SocketInfo) -> bool {
+        match (self, socket) {
+            (&SocketQuery::Port(protocol, port), SocketInfo::Inet(socket)) => {
+                socket.protocol == protocol && socket.local.port() == port
+            }
+            (SocketQuery::Unix(path), SocketInfo::Unix(socket)) => {
+                socket.path.as_ref() == Some(path)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A descriptor holding a socket
+#[derive(Clone, Debug, PartialEq)]
+pub struct SocketOwner {
+    pub pid: u32,
+    pub fd: u32,
+    pub socket: SocketInfo,
+}
+
+/// Find the descriptors which hold the sockets matching a query
+///
+/// The tables are read once for each network namespace, or once for
+/// each process if its namespace can not be told.
+pub fn socket_owners(
+    procfs: &ProcFs,
+    all: &[(u32, u32, FdTarget)],
+    query: &SocketQuery,
+) -> Vec<SocketOwner> {
+    let mut namespaces: HashMap<String, NetTables> = HashMap::new();
+    let mut owners = Vec::new();
+
+    for &(pid, fd, ref target) in all {
+        let inode = match *target {
+            FdTarget::Socket(inode) => inode,
+            _ => {
+                continue;
+            }
+        };
+
+        let namespace = match procfs.read_link(pid, "ns/net") {
+            Ok(link) => link.to_string_lossy().into_owned(),
+            Err(_) => format!("pid {}", pid),
+        };
+        let tables = namespaces
+            .entry(namespace)
+            .or_insert_with(|| NetTables::read_pid(procfs, pid));
+
+        if let Some(socket) = tables.find(inode) {
+            if query.matches(&socket) {
+                owners.push(SocketOwner { pid, fd, socket });
+            }
+        }
+    }
+
+    owners
+}
+
+/// Parse one of /proc/net/{tcp,tcp6,udp,udp6}
+///
+/// Lines that do not parse are skipped.
+pub fn parse_inet(table: &str, protocol: Protocol) -> Vec<InetSocket> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                return None;
+            }
+
+            Some(InetSocket {
+                protocol,
+                local: parse_address(fields[1])?,
+                remote: parse_address(fields[2])?,
+                state: u8::from_str_radix(fields[3], 16).ok()?,
+                uid: fields[7].parse().ok()?,
+                inode: fields[9].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parse an address like `0100007F:1F90`
+///
+/// The address is printed as 32 bit words in host byte order, the
+/// port in plain hexadecimal.
+fn parse_address(text: &str) -> Option<SocketAddr> {
+    let colon = text.find(':')?;
+    let (address, port) = (&text[..colon], &text[(colon + 1)..]);
+    let port = u16::from_str_radix(port, 16).ok()?;
+
+    let mut bytes = Vec::new();
+    for n in 0..(address.len() / 8) {
+        let word = u32::from_str_radix(address.get((n * 8)..(n * 8 + 8))?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+
+    let ip = match bytes.len() {
+        4 => IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])),
+        16 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&bytes);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => {
+            return None;
+        }
+    };
+
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Parse /proc/net/unix
+///
+/// Lines that do not parse are skipped.
+pub fn parse_unix(table: &str) -> Vec<UnixSocket> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 7 {
+                return None;
+            }
+
+            /* The path is the rest of the line and may contain spaces */
+            let path = fields.get(7).map(|first| {
+                let at = first.as_ptr() as usize - line.as_ptr() as usize;
+                PathBuf::from(&line[at..])
+            });
+
+            Some(UnixSocket {
+                flags: u32::from_str_radix(fields[3], 16).ok()?,
+                socket_type: u32::from_str_radix(fields[4], 16).ok()?,
+                state: u8::from_str_radix(fields[5], 16).ok()?,
+                inode: fields[6].parse().ok()?,
+                path,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_inet() {
+        let tcp = parse_inet(include_str!("td/net_tcp_1.txt"), Protocol::Tcp);
+
+        assert_eq!(4, tcp.len());
+        assert_eq!("tcp 127.0.0.1:48271 LISTEN", tcp[0].to_string());
+        assert_eq!(
+            "tcp 127.0.0.1:38972 -> 127.0.0.1:48271 ESTABLISHED",
+            tcp[2].to_string()
+        );
+        assert_eq!(65534, tcp[0].uid);
+
+        let tables = NetTables {
+            inet: tcp,
+            unix: Vec::new(),
+        };
+        assert_eq!(Some(2658), tables.peer(2657));
+        assert_eq!(None, tables.peer(925));
+        assert_eq!(1, tables.listening(Protocol::Tcp, 2024).len());
+    }
+
+    #[test]
+    fn test_parse_inet6() {
+        let tcp6 = parse_inet(
+            "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+             \x20  0: 00000000000000000000000001000000:0016 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1234 1 0000000000000000 100 0 0 10 0\n",
+            Protocol::Tcp,
+        );
+
+        assert_eq!("tcp [::1]:22 LISTEN", tcp6[0].to_string());
+    }
+
+    #[test]
+    fn test_parse_unix() {
+        let unix = parse_unix(
+            "Num       RefCount Protocol Flags    Type St Inode Path\n\
+             00000000c0b39af4: 00000003 00000000 00000000 0001 03   924\n\
+             00000000221c69c2: 00000002 00000000 00010000 0001 01  2609 /tmp/my socket\n\
+             00000000d1a86d10: 00000002 00000000 00000000 0002 01  3000 @abstract\n",
+        );
+
+        assert_eq!(3, unix.len());
+        assert_eq!("unix stream CONNECTED", unix[0].to_string());
+        assert_eq!("unix stream /tmp/my socket LISTEN", unix[1].to_string());
+        assert_eq!("unix dgram @abstract UNCONNECTED", unix[2].to_string());
+    }
+}
@@ -0,0 +1,258 @@
+use std::fs;
+use std::fs::File;
+use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
+
+use error::MupsError;
+use format::{format_args, format_expanded, ArgFormat};
+use response::expand_response_files;
+use stat::ProcStat;
+
+/// A procfs mount to read process information from
+///
+/// Normally this is /proc, but it can as well be the /proc of a
+/// container, a directory with a captured snapshot of one, or a fake
+/// tree made up for testing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcFs {
+    root: PathBuf,
+}
+
+impl ProcFs {
+    pub fn new<P: Into<PathBuf>>(root: P) -> ProcFs {
+        ProcFs { root: root.into() }
+    }
+
+    /// Walk up the process tree from `pid` towards init
+    ///
+    /// The returned list starts with `pid` itself and ends with the
+    /// topmost ancestor that could be found, normally pid 1. Only the
+    /// requested process itself has to exist. An ancestor may exit
+    /// while we are walking up, in which case the list simply ends
+    /// there.
+    pub fn ancestors(&self, pid: u32) -> Result<Vec<u32>, MupsError> {
+        let mut pid = pid;
+        let mut pids = vec![pid];
+
+        loop {
+            let stat = match ProcStat::read_pid(self, pid) {
+                Ok(s) => s,
+                Err(e) => {
+                    if pids.len() == 1 {
+                        return Err(e);
+                    }
+                    break;
+                }
+            };
+
+            /* The parent of init is 0. A snapshot taken while
+             * processes were coming and going might even contain a
+             * loop, which must not keep us going forever. */
+            if stat.ppid == 0 || pids.contains(&stat.ppid) {
+                break;
+            }
+
+            pid = stat.ppid;
+            pids.push(pid);
+        }
+
+        Ok(pids)
+    }
+
+    /// Print the arguments of a process, one argument per line
+    pub fn cmdline_to_stdout(&self, pid: u32, format: &ArgFormat) -> Result<(), MupsError> {
+        let stdout = stdout();
+        let mut out_lock = stdout.lock();
+
+        self.write_cmdline(&mut out_lock, pid, format)
+    }
+
+    /// List the open file descriptors of a process, in order
+    pub fn fds(&self, pid: u32) -> Result<Vec<u32>, MupsError> {
+        numeric_entries(self.pid_path(pid, "fd")).map_err(|e| e.for_pid(pid))
+    }
+
+    /// Path of a file in the directory of a process
+    pub fn pid_path(&self, pid: u32, name: &str) -> PathBuf {
+        self.root.join(pid.to_string()).join(name)
+    }
+
+    /// List the processes that currently exist, in order of pid
+    pub fn pids(&self) -> Result<Vec<u32>, MupsError> {
+        numeric_entries(&self.root)
+    }
+
+    /// Read a file in the directory of a process
+    ///
+    /// A missing file is reported as the process not existing.
+    pub fn read_pid_file(&self, pid: u32, name: &str) -> Result<Vec<u8>, MupsError> {
+        bytes_from_path(self.pid_path(pid, name)).map_err(|e| e.for_pid(pid))
+    }
+
+    /// Read the arguments of a process from /proc/<pid>/cmdline
+    ///
+    /// The arguments are returned as raw bytes since nothing
+    /// guarantees them to be valid UTF-8. Kernel threads have no
+    /// arguments at all.
+    pub fn read_cmdline(&self, pid: u32) -> Result<Vec<Vec<u8>>, MupsError> {
+        let cmdline = self.read_pid_file(pid, "cmdline")?;
+
+        /* Processes that rewrite their argv may leave out the
+         * terminating NUL. */
+        let cmdline = match cmdline.split_last() {
+            Some((&0, init)) => init,
+            _ => &cmdline[..],
+        };
+
+        if cmdline.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(cmdline.split(|b| *b == 0).map(|a| a.to_vec()).collect())
+    }
+
+    /// Read a symbolic link in the directory of a process
+    ///
+    /// Links like exe and cwd have ` (deleted)` appended to the target
+    /// when the file they point to has been removed, which is kept
+    /// as it is.
+    pub fn read_link(&self, pid: u32, name: &str) -> Result<PathBuf, MupsError> {
+        let path = self.pid_path(pid, name);
+
+        match fs::read_link(&path) {
+            Ok(target) => Ok(target),
+            Err(e) => Err(MupsError::from_io(path, e).for_pid(pid)),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// List the threads of a process, in order of thread id
+    ///
+    /// The list includes the main thread, whose id is the pid.
+    pub fn tids(&self, pid: u32) -> Result<Vec<u32>, MupsError> {
+        numeric_entries(self.pid_path(pid, "task")).map_err(|e| e.for_pid(pid))
+    }
+
+    /// Write the arguments of a process, one argument per line
+    ///
+    /// Apart from quoting, the arguments are written out as they
+    /// are, without decoding them in any way.
+    pub fn write_cmdline<W: Write>(
+        &self,
+        out: &mut W,
+        pid: u32,
+        format: &ArgFormat,
+    ) -> Result<(), MupsError> {
+        let args = self.read_cmdline(pid)?;
+
+        if format.expand_response_files {
+            let expanded = expand_response_files(self, pid, &args, format.profile);
+            out.write_all(&format_expanded(&expanded, format))?;
+        } else {
+            out.write_all(&format_args(&args, format))?;
+        }
+        out.write_all(b"\n")?;
+
+        Ok(())
+    }
+}
+
+impl Default for ProcFs {
+    fn default() -> ProcFs {
+        ProcFs::new("/proc")
+    }
+}
+
+pub(crate) fn bytes_from_path<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, MupsError> {
+    use std::io::Read;
+
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            return Err(MupsError::from_io(path, e));
+        }
+    };
+
+    let mut buf = Vec::new();
+
+    if let Err(e) = file.read_to_end(&mut buf) {
+        return Err(MupsError::from_io(path, e));
+    }
+
+    Ok(buf)
+}
+
+/// Entries of a directory that have a number as their name, sorted
+fn numeric_entries<P: AsRef<Path>>(dir: P) -> Result<Vec<u32>, MupsError> {
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Err(MupsError::from_io(dir, e));
+        }
+    };
+
+    let mut numbers = Vec::new();
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                return Err(MupsError::from_io(dir, e));
+            }
+        };
+
+        if let Some(n) = entry
+            .file_name()
+            .to_str()
+            .and_then(|n| n.parse::<u32>().ok())
+        {
+            numbers.push(n);
+        }
+    }
+
+    numbers.sort();
+
+    Ok(numbers)
+}
+
+pub(crate) fn string_from_path<P: AsRef<Path>>(path: P) -> Result<String, MupsError> {
+    let buf = bytes_from_path(&path)?;
+
+    match String::from_utf8(buf) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(MupsError::MalformedProcFile {
+            path: path.as_ref().to_path_buf(),
+            content: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        }),
+    }
+}
+
+/// The path without the ` (deleted)` suffix, if it has one
+pub(crate) fn strip_deleted(path: &[u8]) -> Option<&[u8]> {
+    let suffix = b" (deleted)";
+
+    if path.ends_with(suffix) {
+        Some(&path[..(path.len() - suffix.len())])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_path() {
+        let procfs = ProcFs::new("/run/snapshot/proc");
+
+        assert_eq!(
+            Path::new("/run/snapshot/proc/42/cmdline"),
+            procfs.pid_path(42, "cmdline")
+        );
+        assert_eq!(Path::new("/proc"), ProcFs::default().root());
+    }
+}
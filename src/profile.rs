@@ -0,0 +1,719 @@
+use std::path::Path;
+
+/// Knowledge about the command line syntax of a particular tool
+///
+/// The profile tells which options take a separate value, so that an
+/// option and its value can be kept together when printing, and how
+/// related options can be gathered together.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Profile {
+    /// Anything we know nothing specific about
+    Generic,
+    /// gcc, clang and other compilers with a similar interface
+    Compiler,
+    Java,
+    Python,
+    /// docker and podman
+    Docker,
+    Ssh,
+}
+
+/// Ends of the names of compilers, after any target prefix like in
+/// `x86_64-linux-gnu-g++`
+const COMPILER_NAMES: &[&str] = &[
+    "cc", "c++", "g++", "cpp", "clang", "clang++", "cc1", "cc1plus",
+];
+
+const COMPILER_OPTIONS: &[&str] = &[
+    "-D",
+    "-I",
+    "-L",
+    "-MF",
+    "-MQ",
+    "-MT",
+    "-T",
+    "-U",
+    "-Xassembler",
+    "-Xclang",
+    "-Xlinker",
+    "-Xpreprocessor",
+    "-arch",
+    "-aux-info",
+    "-idirafter",
+    "-imacros",
+    "-include",
+    "-iprefix",
+    "-iquote",
+    "-isysroot",
+    "-isystem",
+    "-iwithprefix",
+    "-l",
+    "-mllvm",
+    "-o",
+    "-target",
+    "-u",
+    "-x",
+    "-z",
+    "--param",
+];
+
+const JAVA_OPTIONS: &[&str] = &[
+    "-classpath",
+    "-cp",
+    "-jar",
+    "-m",
+    "-p",
+    "--add-exports",
+    "--add-modules",
+    "--add-opens",
+    "--add-reads",
+    "--class-path",
+    "--limit-modules",
+    "--module",
+    "--module-path",
+    "--patch-module",
+];
+
+const PYTHON_OPTIONS: &[&str] = &["-W", "-X", "-c", "-m"];
+
+const DOCKER_OPTIONS: &[&str] = &[
+    "-H",
+    "-e",
+    "-f",
+    "-h",
+    "-l",
+    "-m",
+    "-p",
+    "-u",
+    "-v",
+    "-w",
+    "--add-host",
+    "--build-arg",
+    "--cap-add",
+    "--cap-drop",
+    "--config",
+    "--context",
+    "--cpus",
+    "--device",
+    "--dns",
+    "--entrypoint",
+    "--env",
+    "--env-file",
+    "--file",
+    "--host",
+    "--hostname",
+    "--ipc",
+    "--label",
+    "--log-driver",
+    "--log-level",
+    "--log-opt",
+    "--memory",
+    "--mount",
+    "--name",
+    "--net",
+    "--network",
+    "--pid",
+    "--platform",
+    "--publish",
+    "--restart",
+    "--security-opt",
+    "--shm-size",
+    "--tag",
+    "--target",
+    "--tmpfs",
+    "--ulimit",
+    "--user",
+    "--volume",
+    "--workdir",
+];
+
+const SSH_OPTIONS: &[&str] = &[
+    "-B", "-D", "-E", "-F", "-I", "-J", "-L", "-O", "-P", "-Q", "-R", "-S", "-W", "-b", "-c", "-e",
+    "-i", "-l", "-m", "-o", "-p", "-w",
+];
+
+const PROFILE_NAMES: &[(&str, Profile)] = &[
+    ("generic", Profile::Generic),
+    ("compiler", Profile::Compiler),
+    ("java", Profile::Java),
+    ("python", Profile::Python),
+    ("docker", Profile::Docker),
+    ("ssh", Profile::Ssh),
+];
+
+/// Options of java whose value is a list of paths
+const JAVA_PATH_OPTIONS: &[&str] = &[
+    "-classpath",
+    "-cp",
+    "-p",
+    "--class-path",
+    "--module-path",
+    "--upgrade-module-path",
+];
+
+impl Profile {
+    /// Names of the profiles, as accepted by `from_name`
+    pub fn names() -> Vec<&'static str> {
+        PROFILE_NAMES.iter().map(|&(name, _)| name).collect()
+    }
+
+    pub fn from_name(name: &str) -> Option<Profile> {
+        PROFILE_NAMES
+            .iter()
+            .find(|&&(n, _)| n == name)
+            .map(|&(_, profile)| profile)
+    }
+
+    pub fn name(self) -> &'static str {
+        PROFILE_NAMES
+            .iter()
+            .find(|&&(_, p)| p == self)
+            .map(|&(name, _)| name)
+            .unwrap_or("generic")
+    }
+
+    /// Pick a profile based on the name of the program
+    ///
+    /// Version suffixes like in `gcc-12` or `python3.11` and target
+    /// prefixes like in `x86_64-linux-gnu-gcc` are ignored. The
+    /// compiler and linker of MSVC count as compilers too, since they
+    /// read response files the same way.
+    pub fn detect(argv0: &[u8]) -> Profile {
+        let argv0 = String::from_utf8_lossy(argv0);
+        let name = Path::new(argv0.as_ref())
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let name = name
+            .trim_end_matches(".exe")
+            .trim_end_matches(|c: char| c.is_ascii_digit() || c == '.' || c == '-');
+
+        if COMPILER_NAMES.iter().any(|c| name.ends_with(c))
+            || ["cl", "clang-cl", "link", "lld-link"].contains(&name)
+        {
+            Profile::Compiler
+        } else if name == "java" {
+            Profile::Java
+        } else if name == "python" || name == "pypy" {
+            Profile::Python
+        } else if name == "docker" || name == "podman" {
+            Profile::Docker
+        } else if name == "ssh" {
+            Profile::Ssh
+        } else {
+            Profile::Generic
+        }
+    }
+
+    /// How many operands there are before the tool's own options end
+    ///
+    /// After the script of python, the main class of java, the image
+    /// of `docker run` or the destination of ssh, the rest of the
+    /// arguments belong to something else.
+    fn operands(self) -> Option<usize> {
+        match self {
+            Profile::Generic | Profile::Compiler => None,
+            Profile::Java | Profile::Python | Profile::Ssh => Some(1),
+            Profile::Docker => Some(2),
+        }
+    }
+
+    /// Options after which the rest of the arguments belong to
+    /// something else, like the module given to `python -m`
+    fn ends_options(self, option: &str) -> bool {
+        match self {
+            Profile::Java => ["-jar", "-m", "--module"].contains(&option),
+            Profile::Python => ["-c", "-m"].contains(&option),
+            _ => false,
+        }
+    }
+
+    /// Whether `@file` arguments name files with more arguments
+    pub fn reads_response_files(self) -> bool {
+        self == Profile::Compiler || self == Profile::Java
+    }
+
+    /// Whether `option` takes its value from the next argument
+    fn takes_value(self, option: &str) -> bool {
+        let options = match self {
+            Profile::Generic => {
+                return false;
+            }
+            Profile::Compiler => COMPILER_OPTIONS,
+            Profile::Java => JAVA_OPTIONS,
+            Profile::Python => PYTHON_OPTIONS,
+            Profile::Docker => DOCKER_OPTIONS,
+            Profile::Ssh => SSH_OPTIONS,
+        };
+
+        options.contains(&option)
+    }
+}
+
+/// Split arguments into groups that belong together
+///
+/// Each option that takes a separate value is grouped with the value.
+/// Apart from the options the profile knows about, a long option
+/// like `--config` is grouped with the following argument, as long as
+/// that does not look like an option. This does not depend on what
+/// comes after, so that adding an argument to the end leaves the
+/// groups before it as they were.
+pub fn group_args(profile: Profile, args: &[Vec<u8>]) -> Vec<&[Vec<u8>]> {
+    let mut groups = Vec::new();
+    let mut profile = profile;
+    let mut operands = 0;
+    let mut options_done = false;
+    let mut i = 0;
+
+    if !args.is_empty() {
+        groups.push(&args[..1]);
+        i = 1;
+    }
+
+    while i < args.len() {
+        let arg = String::from_utf8_lossy(&args[i]);
+
+        if !options_done && profile.reads_response_files() && arg.starts_with('@') {
+            groups.push(&args[i..(i + 1)]);
+            i += 1;
+            continue;
+        }
+
+        if options_done || !arg.starts_with('-') || arg == "-" {
+            groups.push(&args[i..(i + 1)]);
+            i += 1;
+
+            operands += 1;
+            if profile.operands() == Some(operands) {
+                /* The remote command of ssh is passed on as it is */
+                options_done = profile == Profile::Ssh;
+                profile = Profile::Generic;
+            }
+            continue;
+        }
+
+        if arg == "--" {
+            options_done = true;
+            groups.push(&args[i..(i + 1)]);
+            i += 1;
+            continue;
+        }
+
+        let has_value = if profile.takes_value(&arg) {
+            i + 1 < args.len()
+        } else {
+            arg.starts_with("--")
+                && !arg.contains('=')
+                && i + 1 < args.len()
+                && !args[i + 1].starts_with(b"-")
+                && !args[i + 1].starts_with(b"@")
+        };
+
+        if has_value {
+            groups.push(&args[i..(i + 2)]);
+
+            if profile.ends_options(&arg) {
+                profile = Profile::Generic;
+                operands = 0;
+            }
+            i += 2;
+        } else {
+            groups.push(&args[i..(i + 1)]);
+            i += 1;
+        }
+    }
+
+    groups
+}
+
+/// Kinds of options that are gathered together
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Include,
+    Define,
+    Property,
+    VmOption,
+}
+
+fn option_kind(profile: Profile, option: &[u8]) -> Option<Kind> {
+    let option = String::from_utf8_lossy(option);
+    let starts = |prefixes: &[&str]| prefixes.iter().any(|p| option.starts_with(p));
+
+    match profile {
+        Profile::Compiler => {
+            if starts(&["-I", "-isystem", "-iquote", "-idirafter"]) {
+                Some(Kind::Include)
+            } else if starts(&["-D", "-U"]) {
+                Some(Kind::Define)
+            } else {
+                None
+            }
+        }
+        Profile::Java => {
+            if starts(&["-D"]) {
+                Some(Kind::Property)
+            } else if starts(&["-X"]) {
+                Some(Kind::VmOption)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The name of the macro or property that an option sets
+fn option_key(group: &[Vec<u8>]) -> Vec<u8> {
+    let text = if group[0].len() > 2 || group.len() < 2 {
+        &group[0][2.min(group[0].len())..]
+    } else {
+        &group[1][..]
+    };
+
+    text.split(|&c| c == b'=').next().unwrap_or(b"").to_vec()
+}
+
+/// Gather related options next to each other
+///
+/// For compilers, include directories and macro definitions each end
+/// up in a block of their own, and for java the system properties and
+/// `-X` options. Macros and properties are sorted by their name. The
+/// include directories keep their order, since it decides which
+/// directory wins. Libraries and library directories stay where they
+/// are, since what they mean to the linker depends on the arguments
+/// around them, like `-Wl,--whole-archive` or `-Wl,-Bstatic`.
+///
+/// No option is moved across an `@file` argument, since the file may
+/// undo or override it, like `-UNAME` after `-DNAME`. Only the options
+/// of the tool itself are rearranged; the arguments for the main class
+/// of java are left as they are.
+pub fn arrange_groups<'a>(profile: Profile, groups: &[&'a [Vec<u8>]]) -> Vec<&'a [Vec<u8>]> {
+    let end = match profile {
+        Profile::Compiler => groups.len(),
+        Profile::Java => groups
+            .iter()
+            .skip(1)
+            .position(|g| {
+                let first = String::from_utf8_lossy(&g[0]);
+                !first.starts_with('-') || profile.ends_options(&first)
+            })
+            .map_or(groups.len(), |n| n + 1),
+        _ => {
+            return groups.to_vec();
+        }
+    };
+
+    let kinds: Vec<Option<Kind>> = groups
+        .iter()
+        .enumerate()
+        .map(|(n, g)| {
+            if n == 0 || n >= end {
+                None
+            } else {
+                option_kind(profile, &g[0])
+            }
+        })
+        .collect();
+
+    /* Options are only gathered with others between the same two
+     * response files */
+    let mut segments = Vec::with_capacity(groups.len());
+    let mut segment = 0;
+    for group in groups {
+        if group[0].starts_with(b"@") {
+            segment += 1;
+        }
+        segments.push(segment);
+    }
+
+    let block = |kind: Kind, segment: usize| {
+        let mut members: Vec<&'a [Vec<u8>]> = groups
+            .iter()
+            .enumerate()
+            .filter(|&(n, _)| kinds[n] == Some(kind) && segments[n] == segment)
+            .map(|(_, g)| *g)
+            .collect();
+        if kind == Kind::Define || kind == Kind::Property {
+            /* Stable, so that the last definition of a name still wins */
+            members.sort_by_key(|g| option_key(g));
+        }
+        members
+    };
+
+    let mut arranged = Vec::with_capacity(groups.len());
+
+    for (n, group) in groups.iter().enumerate() {
+        let kind = match kinds[n] {
+            Some(kind) => kind,
+            None => {
+                arranged.push(*group);
+                continue;
+            }
+        };
+
+        let first = (0..n).all(|m| kinds[m] != Some(kind) || segments[m] != segments[n]);
+        if first {
+            arranged.extend(block(kind, segments[n]));
+        }
+    }
+
+    arranged
+}
+
+/// Whether the value of an option is a list of paths
+///
+/// Such values are shown with one path per line.
+pub fn is_path_list(profile: Profile, group: &[Vec<u8>]) -> bool {
+    profile == Profile::Java
+        && group.len() == 2
+        && JAVA_PATH_OPTIONS.contains(&String::from_utf8_lossy(&group[0]).as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grouped(args: &[&str]) -> Vec<String> {
+        let args: Vec<Vec<u8>> = args.iter().map(|a| a.as_bytes().to_vec()).collect();
+        let profile = Profile::detect(&args[0]);
+
+        joined(&group_args(profile, &args))
+    }
+
+    fn arranged(args: &[&str]) -> Vec<String> {
+        let args: Vec<Vec<u8>> = args.iter().map(|a| a.as_bytes().to_vec()).collect();
+        let profile = Profile::detect(&args[0]);
+
+        joined(&arrange_groups(profile, &group_args(profile, &args)))
+    }
+
+    fn joined(groups: &[&[Vec<u8>]]) -> Vec<String> {
+        groups
+            .iter()
+            .map(|g| {
+                let g: Vec<String> = g
+                    .iter()
+                    .map(|a| String::from_utf8_lossy(a).into_owned())
+                    .collect();
+                g.join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Profile::Compiler,
+            Profile::detect(b"/usr/bin/x86_64-linux-gnu-gcc-12")
+        );
+        assert_eq!(Profile::Compiler, Profile::detect(b"clang++-15"));
+        assert_eq!(Profile::Compiler, Profile::detect(b"g++"));
+        assert_eq!(
+            Profile::Compiler,
+            Profile::detect(b"/usr/bin/x86_64-linux-gnu-g++-12")
+        );
+        assert_eq!(Profile::Compiler, Profile::detect(b"gcc-13"));
+        assert_eq!(Profile::Compiler, Profile::detect(b"clang-17"));
+        assert_eq!(Profile::Compiler, Profile::detect(b"C:/VS/bin/CL.EXE"));
+        assert_eq!(Profile::Python, Profile::detect(b"/usr/bin/python3.11"));
+        assert_eq!(Profile::Java, Profile::detect(b"java"));
+        assert_eq!(Profile::Docker, Profile::detect(b"podman"));
+        assert_eq!(Profile::Ssh, Profile::detect(b"ssh"));
+        assert_eq!(Profile::Generic, Profile::detect(b"make"));
+    }
+
+    #[test]
+    fn test_group_compiler() {
+        assert_eq!(
+            vec!["gcc", "-c", "-o out.o", "-I include", "-DX=1", "x.c"],
+            grouped(&["gcc", "-c", "-o", "out.o", "-I", "include", "-DX=1", "x.c"])
+        );
+    }
+
+    #[test]
+    fn test_group_java() {
+        assert_eq!(
+            vec![
+                "java",
+                "-Xmx1g",
+                "-cp a.jar:b.jar",
+                "-jar app.jar",
+                "--port 80"
+            ],
+            grouped(&[
+                "java",
+                "-Xmx1g",
+                "-cp",
+                "a.jar:b.jar",
+                "-jar",
+                "app.jar",
+                "--port",
+                "80"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_group_python() {
+        assert_eq!(
+            vec!["python3", "-W ignore", "-m http.server", "-b", "::"],
+            grouped(&["python3", "-W", "ignore", "-m", "http.server", "-b", "::"])
+        );
+    }
+
+    #[test]
+    fn test_group_docker() {
+        assert_eq!(
+            vec!["docker", "run", "--rm", "-e A=1", "-v /a:/b", "alpine", "sh", "-c", "ls"],
+            grouped(&[
+                "docker", "run", "--rm", "-e", "A=1", "-v", "/a:/b", "alpine", "sh", "-c", "ls"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_group_ssh() {
+        assert_eq!(
+            vec![
+                "ssh",
+                "-p 2222",
+                "-o BatchMode=yes",
+                "host",
+                "--config",
+                "x",
+                "y"
+            ],
+            grouped(&[
+                "ssh",
+                "-p",
+                "2222",
+                "-o",
+                "BatchMode=yes",
+                "host",
+                "--config",
+                "x",
+                "y"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_group_generic() {
+        assert_eq!(
+            vec!["rsync", "--exclude .git", "-a", "--", "--src", "dst"],
+            grouped(&["rsync", "--exclude", ".git", "-a", "--", "--src", "dst"])
+        );
+        assert_eq!(
+            vec!["cp", "--verbose", "-r", "a"],
+            grouped(&["cp", "--verbose", "-r", "a"])
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        for name in Profile::names() {
+            assert_eq!(name, Profile::from_name(name).unwrap().name());
+        }
+        assert_eq!(None, Profile::from_name("auto"));
+    }
+
+    #[test]
+    fn test_group_response_files() {
+        assert_eq!(
+            vec!["gcc", "--save-temps", "@args", "x.c"],
+            grouped(&["gcc", "--save-temps", "@args", "x.c"])
+        );
+        assert_eq!(
+            vec!["java", "@jvm.args", "-jar app.jar"],
+            grouped(&["java", "@jvm.args", "-jar", "app.jar"])
+        );
+    }
+
+    #[test]
+    fn test_arrange_compiler() {
+        assert_eq!(
+            vec![
+                "cc", "-I b", "-Ia", "-DA", "-DB=1", "-UB", "-c", "-Lx", "-lz", "x.o", "-lm",
+                "-o x"
+            ],
+            arranged(&[
+                "cc", "-I", "b", "-DB=1", "-c", "-Ia", "-UB", "-Lx", "-lz", "x.o", "-DA", "-lm",
+                "-o", "x"
+            ])
+        );
+        assert_eq!(
+            vec![
+                "gcc",
+                "main.o",
+                "-Wl,--whole-archive",
+                "-lplugins",
+                "-Wl,--no-whole-archive",
+                "-lm"
+            ],
+            arranged(&[
+                "gcc",
+                "main.o",
+                "-Wl,--whole-archive",
+                "-lplugins",
+                "-Wl,--no-whole-archive",
+                "-lm"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_arrange_response_files() {
+        assert_eq!(
+            vec![
+                "gcc",
+                "-DB",
+                "@undef_a.rsp",
+                "-DA",
+                "-UA",
+                "-DC",
+                "-c",
+                "x.c"
+            ],
+            arranged(&[
+                "gcc",
+                "-DB",
+                "@undef_a.rsp",
+                "-DC",
+                "-DA",
+                "-UA",
+                "-c",
+                "x.c"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_arrange_java() {
+        assert_eq!(
+            vec![
+                "java",
+                "-Da=1",
+                "-Db=2",
+                "-Xss1m",
+                "-Xmx1g",
+                "-cp x.jar",
+                "Main",
+                "-Dz=1",
+                "-Xmx2g"
+            ],
+            arranged(&[
+                "java", "-Db=2", "-Xss1m", "-cp", "x.jar", "-Da=1", "-Xmx1g", "Main", "-Dz=1",
+                "-Xmx2g"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_arrange_generic() {
+        assert_eq!(
+            vec!["make", "-DB", "-C", "dir", "-DA"],
+            arranged(&["make", "-DB", "-C", "dir", "-DA"])
+        );
+    }
+}
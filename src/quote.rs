@@ -0,0 +1,130 @@
+/// How to quote arguments when printing them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quoting {
+    /// Print arguments exactly as they are
+    None,
+    /// Quote arguments so that a POSIX shell reads them back as is
+    Posix,
+    /// Like `Posix`, but use bash `$'...'` strings for arguments
+    /// with control characters or invalid UTF-8, so that the output
+    /// stays printable
+    Bash,
+}
+
+/// Quote one argument
+pub fn quote_arg(arg: &[u8], quoting: Quoting) -> Vec<u8> {
+    if quoting == Quoting::None || (!arg.is_empty() && arg.iter().all(|b| is_safe(*b))) {
+        return arg.to_vec();
+    }
+
+    if quoting == Quoting::Bash && needs_ansi_c(arg) {
+        return ansi_c_quote(arg);
+    }
+
+    let mut quoted = vec![b'\''];
+
+    for &b in arg {
+        if b == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(b);
+        }
+    }
+
+    quoted.push(b'\'');
+    quoted
+}
+
+fn ansi_c_quote(arg: &[u8]) -> Vec<u8> {
+    let mut quoted = Vec::from(&b"$'"[..]);
+    let mut rest = arg;
+
+    while !rest.is_empty() {
+        /* Valid UTF-8 sequences are kept as they are, everything
+         * else that is not plain printable ASCII gets escaped. */
+        let valid_len = match ::std::str::from_utf8(rest) {
+            Ok(s) => s.len(),
+            Err(e) => e.valid_up_to(),
+        };
+
+        let (valid, invalid) = rest.split_at(valid_len);
+
+        for c in String::from_utf8_lossy(valid).chars() {
+            match c {
+                '\n' => quoted.extend_from_slice(b"\\n"),
+                '\t' => quoted.extend_from_slice(b"\\t"),
+                '\r' => quoted.extend_from_slice(b"\\r"),
+                '\\' => quoted.extend_from_slice(b"\\\\"),
+                '\'' => quoted.extend_from_slice(b"\\'"),
+                /* `\xHH` stands for a byte, not a character, so
+                 * the C1 controls take one escape per byte */
+                c if c.is_control() => {
+                    let mut buf = [0; 4];
+                    for b in c.encode_utf8(&mut buf).bytes() {
+                        quoted.extend_from_slice(format!("\\x{:02x}", b).as_bytes());
+                    }
+                }
+                c => {
+                    let mut buf = [0; 4];
+                    quoted.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+
+        if let Some((b, tail)) = invalid.split_first() {
+            quoted.extend_from_slice(format!("\\x{:02x}", b).as_bytes());
+            rest = tail;
+        } else {
+            rest = invalid;
+        }
+    }
+
+    quoted.push(b'\'');
+    quoted
+}
+
+fn is_safe(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"_@%+=:,./-".contains(&b)
+}
+
+fn needs_ansi_c(arg: &[u8]) -> bool {
+    match ::std::str::from_utf8(arg) {
+        Ok(s) => s.chars().any(|c| c.is_control()),
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn posix(arg: &str) -> String {
+        String::from_utf8(quote_arg(arg.as_bytes(), Quoting::Posix)).unwrap()
+    }
+
+    fn bash(arg: &[u8]) -> String {
+        String::from_utf8(quote_arg(arg, Quoting::Bash)).unwrap()
+    }
+
+    #[test]
+    fn test_quote_posix() {
+        assert_eq!("-DFOO=1", posix("-DFOO=1"));
+        assert_eq!("/usr/bin/gcc", posix("/usr/bin/gcc"));
+        assert_eq!("''", posix(""));
+        assert_eq!("'hello world'", posix("hello world"));
+        assert_eq!("'it'\\''s'", posix("it's"));
+        assert_eq!("'$HOME/*.c'", posix("$HOME/*.c"));
+        assert_eq!("'a\nb'", posix("a\nb"));
+        assert_eq!("'~'", posix("~"));
+    }
+
+    #[test]
+    fn test_quote_bash() {
+        assert_eq!("'hello world'", bash(b"hello world"));
+        assert_eq!("$'a\\nb'", bash(b"a\nb"));
+        assert_eq!("$'it\\'s\\x1b[0m'", bash(b"it's\x1b[0m"));
+        assert_eq!("$'caf\\xe9 \\\\ ok'", bash(b"caf\xe9 \\ ok"));
+        assert_eq!("'café'", bash("café".as_bytes()));
+        assert_eq!("$'a\\xc2\\x85b'", bash(b"a\xc2\x85b"));
+    }
+}
@@ -0,0 +1,567 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+/// The largest count allowed in `{m,n}`
+const MAX_REPEAT: usize = 1000;
+
+/// A small regular expression matcher
+///
+/// This supports the commonly used subset of extended regular
+/// expressions: literals, `.`, bracket expressions, the anchors `^`
+/// and `$`, grouping, alternation, the `*`, `+`, `?` and `{m,n}`
+/// quantifiers and the `\d`, `\w` and `\s` classes. The expression
+/// is compiled into a program that is run over all positions of the
+/// text at once, so matching takes no stack and stays linear in the
+/// length of the text, which can be large for Java class paths.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+#[derive(Debug)]
+pub struct RegexError {
+    pattern: String,
+    message: &'static str,
+}
+
+/// One instruction of a compiled expression
+#[derive(Clone, Debug)]
+enum Inst {
+    Any,
+    Char(char),
+    Class(Vec<(char, char)>, bool),
+    End,
+    /// Jump to the given instruction
+    Jump(usize),
+    Match,
+    /// Go on with both of the given instructions
+    Split(usize, usize),
+    Start,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Any,
+    Char(char),
+    Class(Vec<(char, char)>, bool),
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+    Start,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parser = Parser {
+            chars: &chars,
+            pos: 0,
+        };
+
+        let error = |message| RegexError {
+            pattern: String::from(pattern),
+            message,
+        };
+
+        let alternatives = parser.alternatives().map_err(&error)?;
+
+        if parser.pos < chars.len() {
+            return Err(error("unmatched )"));
+        }
+
+        let mut program = Vec::new();
+        compile_alternatives(&alternatives, &mut program);
+        program.push(Inst::Match);
+
+        Ok(Regex { program })
+    }
+
+    /// Build an expression matching the whole text against a glob
+    ///
+    /// The glob may use `*`, `?` and bracket expressions, where `!`
+    /// negates like in the shell.
+    pub fn from_glob(glob: &str) -> Result<Regex, RegexError> {
+        let mut pattern = String::from("^(?:");
+        let mut chars = glob.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '*' => pattern.push_str(".*"),
+                '?' => pattern.push('.'),
+                '[' => {
+                    pattern.push('[');
+                    if chars.peek() == Some(&'!') {
+                        chars.next();
+                        pattern.push('^');
+                    }
+                    /* A ] right at the start is a member of the class */
+                    if chars.peek() == Some(&']') {
+                        chars.next();
+                        pattern.push_str("\\]");
+                    }
+                    for c in chars.by_ref() {
+                        if c == '\\' {
+                            pattern.push('\\');
+                        }
+                        pattern.push(c);
+                        if c == ']' {
+                            break;
+                        }
+                    }
+                }
+                c if c.is_alphanumeric() => pattern.push(c),
+                c => {
+                    pattern.push('\\');
+                    pattern.push(c);
+                }
+            }
+        }
+
+        pattern.push_str(")$");
+        Regex::new(&pattern).map_err(|e| RegexError {
+            pattern: String::from(glob),
+            message: e.message,
+        })
+    }
+
+    /// Whether the expression matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        let input: Vec<char> = text.chars().collect();
+
+        /* The threads waiting at the current position, and the
+         * position each instruction was last added for, plus one */
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        let mut added = vec![0; self.program.len()];
+
+        for pos in 0..=input.len() {
+            /* A match may start anywhere */
+            if self.add_thread(&mut current, &mut added, 0, &input, pos) {
+                return true;
+            }
+
+            if pos == input.len() {
+                break;
+            }
+
+            for &pc in &current {
+                let c = input[pos];
+                let step = match self.program[pc] {
+                    Inst::Any => true,
+                    Inst::Char(expected) => c == expected,
+                    Inst::Class(ref ranges, negated) => {
+                        ranges.iter().any(|&(a, b)| a <= c && c <= b) != negated
+                    }
+                    _ => false,
+                };
+
+                if step && self.add_thread(&mut next, &mut added, pc + 1, &input, pos + 1) {
+                    return true;
+                }
+            }
+
+            current.clear();
+            mem::swap(&mut current, &mut next);
+        }
+
+        false
+    }
+
+    /// Add a thread and those reachable from it without reading input
+    ///
+    /// Only the instructions that read a character end up in the list.
+    /// Returns whether the expression matched.
+    fn add_thread(
+        &self,
+        list: &mut Vec<usize>,
+        added: &mut [usize],
+        pc: usize,
+        input: &[char],
+        pos: usize,
+    ) -> bool {
+        let mut stack = vec![pc];
+
+        while let Some(pc) = stack.pop() {
+            if added[pc] == pos + 1 {
+                continue;
+            }
+            added[pc] = pos + 1;
+
+            match self.program[pc] {
+                Inst::Match => {
+                    return true;
+                }
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+                Inst::Start => {
+                    if pos == 0 {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::End => {
+                    if pos == input.len() {
+                        stack.push(pc + 1);
+                    }
+                }
+                _ => list.push(pc),
+            }
+        }
+
+        false
+    }
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid regular expression {:?}: {}",
+            self.pattern, self.message
+        )
+    }
+}
+
+impl Error for RegexError {}
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, &'static str> {
+        let mut alternatives = vec![self.sequence()?];
+
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+
+        Ok(alternatives)
+    }
+
+    fn atom(&mut self) -> Result<Node, &'static str> {
+        let c = self.next().ok_or("unexpected end")?;
+
+        match c {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '(' => {
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+
+                let alternatives = self.alternatives()?;
+
+                match self.next() {
+                    Some(')') => Ok(Node::Group(alternatives)),
+                    _ => Err("unmatched ("),
+                }
+            }
+            '[' => self.class(),
+            '\\' => {
+                let c = self.next().ok_or("trailing backslash")?;
+                Ok(escape_class(c).unwrap_or_else(|| Node::Char(escape_char(c))))
+            }
+            '*' | '+' | '?' => Err("quantifier without anything to repeat"),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn class(&mut self) -> Result<Node, &'static str> {
+        let mut ranges = Vec::new();
+        let mut negated = false;
+
+        if self.peek() == Some('^') {
+            negated = true;
+            self.pos += 1;
+        }
+
+        let mut first = true;
+
+        loop {
+            let c = self.next().ok_or("unmatched [")?;
+
+            match c {
+                ']' if !first => break,
+                '\\' => {
+                    let c = self.next().ok_or("unmatched [")?;
+
+                    match escape_class(c) {
+                        Some(Node::Class(r, false)) => ranges.extend(r),
+                        Some(_) => return Err("negated class inside brackets"),
+                        None => {
+                            let c = escape_char(c);
+                            ranges.push((c, c));
+                        }
+                    }
+                }
+                c => {
+                    if self.peek() == Some('-')
+                        && self.chars.get(self.pos + 1).is_some_and(|e| *e != ']')
+                    {
+                        let end = self.chars[self.pos + 1];
+                        self.pos += 2;
+
+                        if end < c {
+                            return Err("invalid range");
+                        }
+                        ranges.push((c, end));
+                    } else {
+                        ranges.push((c, c));
+                    }
+                }
+            }
+
+            first = false;
+        }
+
+        Ok(Node::Class(ranges, negated))
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn quantifier(&mut self) -> Result<Option<(usize, Option<usize>)>, &'static str> {
+        let bounds = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.pos += 1;
+
+                let min = self.number().ok_or("invalid repetition")?;
+                let max = if self.peek() == Some(',') {
+                    self.pos += 1;
+                    self.number()
+                } else {
+                    Some(min)
+                };
+
+                if self.peek() != Some('}') || max.is_some_and(|m| m < min) {
+                    return Err("invalid repetition");
+                }
+
+                /* Each repetition is a copy of the expression in the
+                 * program, so like RE2 we keep the counts small */
+                if max.unwrap_or(min) > MAX_REPEAT {
+                    return Err("repetition count too large");
+                }
+
+                (min, max)
+            }
+            _ => {
+                return Ok(None);
+            }
+        };
+
+        self.pos += 1;
+
+        /* Lazy quantifiers make no difference when all we need to
+         * know is whether there is a match at all. */
+        if self.peek() == Some('?') {
+            self.pos += 1;
+        }
+
+        Ok(Some(bounds))
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, &'static str> {
+        let mut nodes = Vec::new();
+
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+
+            let atom = self.atom()?;
+
+            match self.quantifier()? {
+                Some((min, max)) => nodes.push(Node::Repeat(Box::new(atom), min, max)),
+                None => nodes.push(atom),
+            }
+        }
+
+        Ok(nodes)
+    }
+}
+
+fn escape_char(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        c => c,
+    }
+}
+
+fn escape_class(c: char) -> Option<Node> {
+    let ranges = match c.to_ascii_lowercase() {
+        'd' => vec![('0', '9')],
+        'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+        's' => vec![(' ', ' '), ('\t', '\r')],
+        _ => {
+            return None;
+        }
+    };
+
+    Some(Node::Class(ranges, c.is_ascii_uppercase()))
+}
+
+fn compile_alternatives(alternatives: &[Vec<Node>], program: &mut Vec<Inst>) {
+    let mut jumps = Vec::new();
+
+    for (n, seq) in alternatives.iter().enumerate() {
+        let last = n + 1 == alternatives.len();
+        let split = program.len();
+
+        if !last {
+            program.push(Inst::Split(split + 1, 0));
+        }
+
+        for node in seq {
+            compile_node(node, program);
+        }
+
+        if !last {
+            jumps.push(program.len());
+            program.push(Inst::Jump(0));
+            program[split] = Inst::Split(split + 1, program.len());
+        }
+    }
+
+    for jump in jumps {
+        program[jump] = Inst::Jump(program.len());
+    }
+}
+
+fn compile_node(node: &Node, program: &mut Vec<Inst>) {
+    match *node {
+        Node::Any => program.push(Inst::Any),
+        Node::Char(c) => program.push(Inst::Char(c)),
+        Node::Class(ref ranges, negated) => program.push(Inst::Class(ranges.clone(), negated)),
+        Node::End => program.push(Inst::End),
+        Node::Group(ref alternatives) => compile_alternatives(alternatives, program),
+        Node::Repeat(ref node, min, max) => {
+            for _ in 0..min {
+                compile_node(node, program);
+            }
+
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile_node(node, program);
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+
+                    for _ in min..max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile_node(node, program);
+                    }
+
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, program.len());
+                    }
+                }
+            }
+        }
+        Node::Start => program.push(Inst::Start),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_regex_match() {
+        assert!(matches("nginx", "/usr/sbin/nginx -g daemon"));
+        assert!(matches("^/usr/s?bin/ngin.", "/usr/sbin/nginx"));
+        assert!(!matches("^nginx", "/usr/sbin/nginx"));
+        assert!(matches("-j[0-9]+$", "make -j16"));
+        assert!(!matches("-j[0-9]+$", "make -j16 all"));
+        assert!(matches("(gcc|clang)\\s+-c", "clang  -c x.c"));
+        assert!(matches("a{2,3}b", "xaab"));
+        assert!(!matches("^a{2,3}b", "ab"));
+        assert!(matches("[^a-z]\\.py$", "/X.py"));
+        assert!(matches("(a*)+$", ""));
+        assert!(matches("", "anything"));
+    }
+
+    #[test]
+    fn test_regex_invalid() {
+        assert!(Regex::new("(abc").is_err());
+        assert!(Regex::new("abc)").is_err());
+        assert!(Regex::new("[abc").is_err());
+        assert!(Regex::new("*abc").is_err());
+        assert!(Regex::new("a{3,1}").is_err());
+        assert!(Regex::new("a{1001}").is_err());
+        assert!(Regex::new("a{1,100000}").is_err());
+        assert!(Regex::new("a{100000,}").is_err());
+        assert!(Regex::new("a{1000}").is_ok());
+    }
+
+    #[test]
+    fn test_regex_long_input() {
+        let mut cmdline = String::from("java -cp ");
+        for n in 0..20000 {
+            cmdline.push_str(&format!("/opt/lib/dep-{}.jar:", n));
+        }
+        cmdline.push_str(" com.example.Main");
+
+        assert!(matches("java.*Main", &cmdline));
+        assert!(matches("^java .*dep-19999\\.jar", &cmdline));
+        assert!(!matches("java.*Main$x", &cmdline));
+    }
+
+    #[test]
+    fn test_glob() {
+        let glob = Regex::from_glob("LC_*").unwrap();
+        assert!(glob.is_match("LC_ALL"));
+        assert!(!glob.is_match("XLC_ALL"));
+
+        let glob = Regex::from_glob("?[!a-c]x.[ch]").unwrap();
+        assert!(glob.is_match("zdx.c"));
+        assert!(!glob.is_match("zax.c"));
+        assert!(!glob.is_match("zdxxc"));
+
+        assert!(Regex::from_glob("[abc").is_err());
+    }
+}
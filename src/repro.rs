@@ -0,0 +1,169 @@
+use std::os::unix::ffi::OsStrExt;
+
+use environ::{split_var, Environ};
+use error::MupsError;
+use format::{format_args, ArgFormat};
+use limits::ProcLimits;
+use procfs::{strip_deleted, ProcFs};
+use profile::Profile;
+use quote::{quote_arg, Quoting};
+use stat::ProcStat;
+use status::ProcStatus;
+
+/// Options of the bash ulimit builtin for the limits in /proc/<pid>/limits
+///
+/// The last field tells how many bytes one unit of ulimit is, for
+/// the limits which the kernel shows in bytes.
+const ULIMIT_OPTIONS: &[(&str, &str, u64)] = &[
+    ("Max cpu time", "-t", 1),
+    ("Max file size", "-f", 1024),
+    ("Max data size", "-d", 1024),
+    ("Max stack size", "-s", 1024),
+    ("Max core file size", "-c", 1024),
+    ("Max resident set", "-m", 1024),
+    ("Max processes", "-u", 1),
+    ("Max open files", "-n", 1),
+    ("Max locked memory", "-l", 1024),
+    ("Max address space", "-v", 1024),
+    ("Max file locks", "-x", 1),
+    ("Max pending signals", "-i", 1),
+    ("Max msgqueue size", "-q", 1),
+    ("Max nice priority", "-e", 1),
+    ("Max realtime priority", "-r", 1),
+];
+
+/// Write a bash script which starts the process again the same way
+///
+/// The script changes to the working directory of the process, sets
+/// the umask and resource limits, replaces its own environment with
+/// the one the process started with and finally execs the executable
+/// of the process with the same arguments, argv[0] included. Setting
+/// limits may fail without root, in which case bash complains and the
+/// script goes on.
+pub fn repro_script(procfs: &ProcFs, pid: u32, quoting: Quoting) -> Result<Vec<u8>, MupsError> {
+    let stat = ProcStat::read_pid(procfs, pid)?;
+    let args = procfs.read_cmdline(pid)?;
+
+    if args.is_empty() {
+        return Err(MupsError::BadInput(format!(
+            "process {} is a kernel thread",
+            pid
+        )));
+    }
+
+    let status = ProcStatus::read_pid(procfs, pid)?;
+    let limits = ProcLimits::read_pid(procfs, pid)?;
+    let environ = Environ::read_pid(procfs, pid)?;
+
+    let quote = |bytes: &[u8]| quote_arg(bytes, quoting);
+    let mut out = Vec::new();
+
+    out.extend_from_slice(b"#!/bin/bash\n");
+    out.extend_from_slice(format!("# Recreated from process {}: ", pid).as_bytes());
+    out.extend_from_slice(&quote_arg(stat.comm.as_bytes(), Quoting::Bash));
+    out.push(b'\n');
+
+    match procfs.read_link(pid, "cwd") {
+        Ok(cwd) => {
+            out.extend_from_slice(b"cd ");
+            out.extend_from_slice(&quote(cwd.as_os_str().as_bytes()));
+            out.extend_from_slice(b" || exit 1\n");
+        }
+        Err(e) => {
+            out.extend_from_slice(format!("# no working directory: {}\n", e).as_bytes());
+        }
+    }
+
+    if let Some(umask) = status.get("Umask") {
+        out.extend_from_slice(format!("umask {}\n", umask).as_bytes());
+    }
+
+    for &(name, option, unit) in ULIMIT_OPTIONS {
+        let limit = match limits.get(name) {
+            Some(limit) => limit,
+            None => {
+                continue;
+            }
+        };
+
+        let value = |v: Option<u64>| match v {
+            Some(v) => (v / unit).to_string(),
+            None => String::from("unlimited"),
+        };
+
+        let line = if limit.soft == limit.hard {
+            format!("ulimit {} {}\n", option, value(limit.hard))
+        } else {
+            format!(
+                "ulimit -H {0} {1}\nulimit -S {0} {2}\n",
+                option,
+                value(limit.hard),
+                value(limit.soft)
+            )
+        };
+        out.extend_from_slice(line.as_bytes());
+    }
+
+    out.extend_from_slice(b"for var in $(compgen -e); do unset \"$var\" 2>/dev/null; done\n");
+
+    for entry in environ.entries() {
+        let (name, value) = split_var(entry);
+
+        let valid_name = !name.is_empty()
+            && !name[0].is_ascii_digit()
+            && name.iter().all(|&c| c.is_ascii_alphanumeric() || c == b'_');
+
+        match value {
+            Some(value) if valid_name => {
+                out.extend_from_slice(b"export ");
+                out.extend_from_slice(name);
+                out.push(b'=');
+                out.extend_from_slice(&quote(value));
+                out.push(b'\n');
+            }
+            _ => {
+                /* Bash has no way to pass these on */
+                out.extend_from_slice(b"# can not set: ");
+                out.extend_from_slice(&quote_arg(entry, Quoting::Bash));
+                out.push(b'\n');
+            }
+        }
+    }
+
+    let exe = match procfs.read_link(pid, "exe") {
+        Ok(exe) => {
+            let exe = exe.as_os_str().as_bytes();
+            match strip_deleted(exe) {
+                Some(exe) => {
+                    out.extend_from_slice(b"# the executable has been deleted since\n");
+                    exe.to_vec()
+                }
+                None => exe.to_vec(),
+            }
+        }
+        Err(_) => args[0].clone(),
+    };
+
+    out.extend_from_slice(b"exec ");
+    if exe != args[0] {
+        out.extend_from_slice(b"-a ");
+        out.extend_from_slice(&quote(&args[0]));
+        out.push(b' ');
+    }
+
+    let mut argv = vec![exe];
+    argv.extend_from_slice(&args[1..]);
+
+    /* Options must stay in their order, so no profile specific
+     * rearranging here */
+    let format = ArgFormat {
+        quoting,
+        grouping: true,
+        profile: Some(Profile::Generic),
+        expand_response_files: false,
+    };
+    out.extend_from_slice(&format_args(&argv, &format));
+    out.push(b'\n');
+
+    Ok(out)
+}
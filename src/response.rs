@@ -0,0 +1,394 @@
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use json::{bytes_fields, Json};
+use procfs::ProcFs;
+use profile::Profile;
+
+/// How deep response files may refer to other response files
+const MAX_DEPTH: usize = 16;
+
+/// How many response files are read for one argument list at most
+const MAX_FILES: usize = 1000;
+
+/// How many arguments response files may add up to
+const MAX_ARGS: usize = 100_000;
+
+/// How large a response file may be to be read
+const MAX_FILE_SIZE: u64 = 4 << 20;
+
+/// Quoting rules used inside response files
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Syntax {
+    /// gcc and other tools using libiberty, and java
+    Gnu,
+    /// cl.exe and link.exe, with the rules of CommandLineToArgvW
+    Msvc,
+}
+
+impl Syntax {
+    /// Pick the rules based on the name of the program
+    pub fn detect(argv0: &[u8]) -> Syntax {
+        let argv0 = String::from_utf8_lossy(argv0);
+        let name = Path::new(argv0.as_ref())
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let name = name.trim_end_matches(".exe");
+
+        if ["cl", "clang-cl", "link", "lld-link"].contains(&name) {
+            Syntax::Msvc
+        } else {
+            Syntax::Gnu
+        }
+    }
+}
+
+/// A response file that was read in place of an `@file` argument
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResponseFile {
+    /// The argument as it was given
+    pub arg: Vec<u8>,
+    /// Where the file is, as seen by the process
+    pub path: PathBuf,
+    /// How many response files this one is nested in
+    pub depth: usize,
+    /// Where the arguments from the file start in the expanded list
+    pub start: usize,
+    /// How many arguments the file gave, including nested files
+    pub len: usize,
+}
+
+/// An argument list with the response files read in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expanded {
+    pub args: Vec<Vec<u8>>,
+    /// The files in the order they appear in the arguments
+    pub files: Vec<ResponseFile>,
+}
+
+impl Expanded {
+    /// Describe the response files for JSON output
+    pub fn files_json(&self) -> Json {
+        let files: Vec<Json> = self
+            .files
+            .iter()
+            .map(|f| {
+                let object = Json::object()
+                    .field("arg", String::from_utf8_lossy(&f.arg).into_owned())
+                    .field("path", f.path.to_string_lossy().into_owned())
+                    .field("depth", f.depth as i64)
+                    .field("start", f.start as i64);
+                bytes_fields(object, "argv", &self.args[f.start..(f.start + f.len)])
+            })
+            .collect();
+
+        Json::Array(files)
+    }
+}
+
+/// Replace `@file` arguments of a process with the contents of the files
+///
+/// Relative names are looked up from the working directory of the
+/// process, like the tools themselves do, also for the `@file`
+/// arguments inside response files. Like gcc, an argument is left as
+/// it is if there is no file to read.
+///
+/// Only programs whose profile reads response files get their
+/// arguments expanded, with `profile` or else the profile detected
+/// from argv[0], since to others `@file` may mean something else.
+/// A file that refers to itself, directly or through other files, is
+/// not read again.
+pub fn expand_response_files(
+    procfs: &ProcFs,
+    pid: u32,
+    args: &[Vec<u8>],
+    profile: Option<Profile>,
+) -> Expanded {
+    let (syntax, profile) = match args.first() {
+        Some(argv0) => (
+            Syntax::detect(argv0),
+            profile.unwrap_or_else(|| Profile::detect(argv0)),
+        ),
+        None => (Syntax::Gnu, Profile::Generic),
+    };
+
+    if !profile.reads_response_files() {
+        return Expanded {
+            args: args.to_vec(),
+            files: Vec::new(),
+        };
+    }
+
+    let mut expanded = Expanded {
+        args: Vec::new(),
+        files: Vec::new(),
+    };
+    let mut stack = Vec::new();
+
+    for (n, arg) in args.iter().enumerate() {
+        if n == 0 {
+            expanded.args.push(arg.clone());
+        } else {
+            expand_arg(procfs, pid, syntax, arg, &mut stack, &mut expanded);
+        }
+    }
+
+    expanded
+}
+
+/// Read a response file, unless it is not a regular file or too large
+///
+/// A name like `@/dev/zero` or `@fifo` must not make us read forever.
+fn read_response_file(path: &Path) -> Option<Vec<u8>> {
+    if !fs::metadata(path).ok()?.is_file() {
+        return None;
+    }
+
+    let mut text = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(MAX_FILE_SIZE + 1)
+        .read_to_end(&mut text)
+        .ok()?;
+
+    if text.len() as u64 > MAX_FILE_SIZE {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Expand one argument, from within the files on `stack`
+fn expand_arg(
+    procfs: &ProcFs,
+    pid: u32,
+    syntax: Syntax,
+    arg: &[u8],
+    stack: &mut Vec<PathBuf>,
+    expanded: &mut Expanded,
+) {
+    if arg.len() < 2
+        || arg[0] != b'@'
+        || stack.len() >= MAX_DEPTH
+        || expanded.files.len() >= MAX_FILES
+        || expanded.args.len() >= MAX_ARGS
+    {
+        expanded.args.push(arg.to_vec());
+        return;
+    }
+
+    let name = Path::new(OsStr::from_bytes(&arg[1..]));
+    let (path, source) = if name.is_absolute() {
+        let source = procfs
+            .pid_path(pid, "root")
+            .join(name.strip_prefix("/").unwrap_or(name));
+        (name.to_path_buf(), source)
+    } else {
+        let path = match procfs.read_link(pid, "cwd") {
+            Ok(cwd) => cwd.join(name),
+            Err(_) => name.to_path_buf(),
+        };
+        (path, procfs.pid_path(pid, "cwd").join(name))
+    };
+
+    /* The same file may be named in different ways */
+    let source = fs::canonicalize(&source).unwrap_or(source);
+    if stack.contains(&source) {
+        expanded.args.push(arg.to_vec());
+        return;
+    }
+
+    let text = match read_response_file(&source) {
+        Some(text) => text,
+        None => {
+            expanded.args.push(arg.to_vec());
+            return;
+        }
+    };
+
+    let index = expanded.files.len();
+    let start = expanded.args.len();
+    expanded.files.push(ResponseFile {
+        arg: arg.to_vec(),
+        path,
+        depth: stack.len(),
+        start,
+        len: 0,
+    });
+
+    stack.push(source);
+    for word in split_response_file(&text, syntax) {
+        expand_arg(procfs, pid, syntax, &word, stack, expanded);
+    }
+    stack.pop();
+
+    expanded.files[index].len = expanded.args.len() - start;
+}
+
+/// Split the contents of a response file into arguments
+pub fn split_response_file(text: &[u8], syntax: Syntax) -> Vec<Vec<u8>> {
+    match syntax {
+        Syntax::Gnu => split_gnu(text),
+        Syntax::Msvc => split_msvc(text),
+    }
+}
+
+/// Split like `buildargv` of libiberty
+///
+/// Single and double quotes work like in a shell, but a backslash
+/// escapes the next character everywhere.
+fn split_gnu(text: &[u8]) -> Vec<Vec<u8>> {
+    let mut words = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < text.len() && text[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == text.len() {
+            break;
+        }
+
+        let mut word = Vec::new();
+        let mut quote = None;
+
+        while i < text.len() {
+            let c = text[i];
+            i += 1;
+
+            if c == b'\\' && i < text.len() {
+                word.push(text[i]);
+                i += 1;
+            } else if Some(c) == quote {
+                quote = None;
+            } else if quote.is_some() {
+                word.push(c);
+            } else if c == b'\'' || c == b'"' {
+                quote = Some(c);
+            } else if c.is_ascii_whitespace() {
+                break;
+            } else {
+                word.push(c);
+            }
+        }
+
+        words.push(word);
+    }
+
+    words
+}
+
+/// Split like `CommandLineToArgvW`
+///
+/// Only double quotes quote. Backslashes are taken literally, unless
+/// they come before a double quote, in which case each pair of them
+/// turns into one backslash and an odd one escapes the quote.
+fn split_msvc(text: &[u8]) -> Vec<Vec<u8>> {
+    let mut words = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < text.len() && text[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == text.len() {
+            break;
+        }
+
+        let mut word = Vec::new();
+        let mut quoted = false;
+
+        while i < text.len() {
+            let c = text[i];
+
+            if c == b'\\' {
+                let backslashes = text[i..].iter().take_while(|&&c| c == b'\\').count();
+                i += backslashes;
+
+                if i < text.len() && text[i] == b'"' {
+                    word.extend(vec![b'\\'; backslashes / 2]);
+                    if backslashes % 2 == 1 {
+                        word.push(b'"');
+                        i += 1;
+                    }
+                } else {
+                    word.extend(vec![b'\\'; backslashes]);
+                }
+                continue;
+            }
+
+            i += 1;
+
+            if c == b'"' {
+                if quoted && i < text.len() && text[i] == b'"' {
+                    /* A doubled quote inside quotes is a literal one */
+                    word.push(b'"');
+                    i += 1;
+                } else {
+                    quoted = !quoted;
+                }
+            } else if !quoted && c.is_ascii_whitespace() {
+                break;
+            } else {
+                word.push(c);
+            }
+        }
+
+        words.push(word);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(text: &str, syntax: Syntax) -> Vec<String> {
+        split_response_file(text.as_bytes(), syntax)
+            .iter()
+            .map(|w| String::from_utf8_lossy(w).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Syntax::Msvc, Syntax::detect(b"C:/VS/bin/CL.EXE"));
+        assert_eq!(Syntax::Msvc, Syntax::detect(b"lld-link"));
+        assert_eq!(Syntax::Gnu, Syntax::detect(b"/usr/bin/gcc"));
+    }
+
+    #[test]
+    fn test_split_gnu() {
+        assert_eq!(
+            vec!["-DMSG=\"hi there\"", "-I", "a b", "it's", "", "x\\y"],
+            split(
+                "-DMSG='\"hi there\"'\n  -I \"a b\"\tit\\'s '' x\\\\y\n",
+                Syntax::Gnu
+            )
+        );
+    }
+
+    #[test]
+    fn test_split_msvc() {
+        assert_eq!(
+            vec![
+                "/Fo\"out dir\\\"",
+                "C:\\a b\\",
+                "say \"hi\"",
+                "'x",
+                "y'",
+                "\\\\server"
+            ],
+            split(
+                "/Fo\\\"\"out dir\\\\\"\\\" \"C:\\a b\\\\\"\r\n\"say \"\"hi\"\"\" 'x y' \\\\server",
+                Syntax::Msvc
+            )
+        );
+    }
+}
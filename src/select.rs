@@ -0,0 +1,193 @@
+use std::path::{Path, PathBuf};
+
+use error::MupsError;
+use procfs::{string_from_path, ProcFs};
+use regex::Regex;
+use stat::ProcStat;
+use status::ProcStatus;
+
+/// Which of the matching processes to keep
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pick {
+    All,
+    Newest,
+    Oldest,
+}
+
+/// Criteria for finding processes, similar to those of pgrep
+///
+/// A process is selected when it matches all of the criteria that
+/// are given. With no criteria at all, every process is selected.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    /// Only consider these processes, if any are given
+    pub pids: Vec<u32>,
+    /// Never select these processes, typically mups itself
+    pub exclude: Vec<u32>,
+    /// Name of the process, as in comm or the basename of argv[0]
+    pub name: Option<String>,
+    /// Pattern for the arguments, joined with spaces
+    pub cmdline: Option<Regex>,
+    /// Real user id of the process
+    pub uid: Option<u32>,
+    /// The executable, either a full path or just a file name
+    pub exe: Option<PathBuf>,
+    /// Parent process id
+    pub parent: Option<u32>,
+    pub pick: Pick,
+}
+
+impl Default for Selector {
+    fn default() -> Selector {
+        Selector {
+            pids: Vec::new(),
+            exclude: Vec::new(),
+            name: None,
+            cmdline: None,
+            uid: None,
+            exe: None,
+            parent: None,
+            pick: Pick::All,
+        }
+    }
+}
+
+impl Selector {
+    /// Find the matching processes, in order of pid
+    ///
+    /// Processes that were explicitly asked for by pid have to exist.
+    /// Other processes that go away while we look at them are simply
+    /// not selected.
+    pub fn select(&self, procfs: &ProcFs) -> Result<Vec<u32>, MupsError> {
+        let candidates = if self.pids.is_empty() {
+            procfs.pids()?
+        } else {
+            self.pids.clone()
+        };
+
+        let mut selected: Vec<ProcStat> = Vec::new();
+
+        for pid in candidates {
+            if self.exclude.contains(&pid) {
+                continue;
+            }
+
+            let stat = match ProcStat::read_pid(procfs, pid) {
+                Ok(stat) => stat,
+                Err(MupsError::NoSuchProcess(_)) if self.pids.is_empty() => {
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            };
+
+            match self.matches(procfs, &stat) {
+                Ok(true) => selected.push(stat),
+                Ok(false) | Err(MupsError::NoSuchProcess(_)) => {}
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+
+        let picked = match self.pick {
+            Pick::All => None,
+            Pick::Newest => selected.iter().max_by_key(|s| (s.starttime, s.pid)),
+            Pick::Oldest => selected.iter().min_by_key(|s| (s.starttime, s.pid)),
+        };
+
+        match picked {
+            Some(stat) => Ok(vec![stat.pid]),
+            None => Ok(selected.iter().map(|s| s.pid).collect()),
+        }
+    }
+
+    fn matches(&self, procfs: &ProcFs, stat: &ProcStat) -> Result<bool, MupsError> {
+        let pid = stat.pid;
+
+        if let Some(parent) = self.parent {
+            if stat.ppid != parent {
+                return Ok(false);
+            }
+        }
+
+        if let Some(uid) = self.uid {
+            if ProcStatus::read_pid(procfs, pid)?.uid() != Some(uid) {
+                return Ok(false);
+            }
+        }
+
+        if self.name.is_some() || self.cmdline.is_some() {
+            let args: Vec<String> = procfs
+                .read_cmdline(pid)?
+                .iter()
+                .map(|a| String::from_utf8_lossy(a).into_owned())
+                .collect();
+
+            if let Some(ref name) = self.name {
+                let arg0_name = args
+                    .first()
+                    .and_then(|a| Path::new(a).file_name())
+                    .and_then(|n| n.to_str());
+
+                if *name != stat.comm && Some(name.as_str()) != arg0_name {
+                    return Ok(false);
+                }
+            }
+
+            if let Some(ref cmdline) = self.cmdline {
+                if !cmdline.is_match(&args.join(" ")) {
+                    return Ok(false);
+                }
+            }
+        }
+
+        if let Some(ref exe) = self.exe {
+            /* The executable of other users' processes cannot be
+             * looked at, so those do not match. */
+            let target = match procfs.read_link(pid, "exe") {
+                Ok(target) => target,
+                Err(MupsError::PermissionDenied(_)) => {
+                    return Ok(false);
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            };
+
+            let matched = if exe.components().count() > 1 {
+                target == *exe
+            } else {
+                target.file_name() == Some(exe.as_os_str())
+            };
+
+            if !matched {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Look up the user id for a user name or a numeric uid
+///
+/// Names are looked up from /etc/passwd, without going through NSS.
+pub fn lookup_uid(user: &str) -> Result<Option<u32>, MupsError> {
+    if let Ok(uid) = user.parse::<u32>() {
+        return Ok(Some(uid));
+    }
+
+    let passwd = string_from_path("/etc/passwd")?;
+
+    Ok(passwd.lines().find_map(|line| {
+        let mut fields = line.split(':');
+
+        if fields.next() != Some(user) {
+            return None;
+        }
+
+        fields.nth(1)?.parse().ok()
+    }))
+}
@@ -0,0 +1,265 @@
+use std::error::Error;
+use std::fmt;
+
+/// Input that could not be split into words
+#[derive(Debug, PartialEq)]
+pub struct ShellSyntaxError {
+    message: &'static str,
+}
+
+impl fmt::Display for ShellSyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ShellSyntaxError {}
+
+/// Split a command line into words the way a POSIX shell would
+///
+/// Quotes and backslashes are removed, and bash style `$'...'`
+/// strings are decoded. Nothing gets expanded, so `$HOME` or `*.c`
+/// stay as they are. A backslash at the end of a line continues the
+/// line, so already prettified command lines can be read back.
+/// Separate lines are taken as one long command line.
+pub fn split_words(input: &str) -> Result<Vec<Vec<u8>>, ShellSyntaxError> {
+    Ok(split_commands(input)?.into_iter().flatten().collect())
+}
+
+/// Split input with one command line per line into words
+///
+/// This works like `split_words`, but keeps the words of each line
+/// apart. Lines without any words are skipped.
+pub fn split_commands(input: &str) -> Result<Vec<Vec<Vec<u8>>>, ShellSyntaxError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    let mut word: Option<Vec<u8>> = None;
+    let mut pos = 0;
+
+    fn push(word: &mut Option<Vec<u8>>, c: char) {
+        let mut buf = [0; 4];
+        word.get_or_insert_with(Vec::new)
+            .extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        pos += 1;
+
+        match c {
+            ' ' | '\t' | '\n' => {
+                if let Some(w) = word.take() {
+                    words.push(w);
+                }
+                if c == '\n' && !words.is_empty() {
+                    commands.push(words);
+                    words = Vec::new();
+                }
+            }
+            '#' if word.is_none() => {
+                while pos < chars.len() && chars[pos] != '\n' {
+                    pos += 1;
+                }
+            }
+            '\\' => match chars.get(pos) {
+                Some('\n') => pos += 1,
+                Some(&c) => {
+                    push(&mut word, c);
+                    pos += 1;
+                }
+                None => {
+                    return Err(ShellSyntaxError {
+                        message: "backslash at end of input",
+                    });
+                }
+            },
+            '\'' => {
+                word.get_or_insert_with(Vec::new);
+
+                loop {
+                    match chars.get(pos) {
+                        Some('\'') => break,
+                        Some(&c) => push(&mut word, c),
+                        None => {
+                            return Err(ShellSyntaxError {
+                                message: "unterminated single quote",
+                            });
+                        }
+                    }
+                    pos += 1;
+                }
+                pos += 1;
+            }
+            '"' => {
+                word.get_or_insert_with(Vec::new);
+
+                loop {
+                    match chars.get(pos) {
+                        Some('"') => break,
+                        Some('\\') => match chars.get(pos + 1) {
+                            Some('\n') => pos += 1,
+                            Some(&c) if "$`\"\\".contains(c) => {
+                                push(&mut word, c);
+                                pos += 1;
+                            }
+                            _ => push(&mut word, '\\'),
+                        },
+                        Some(&c) => push(&mut word, c),
+                        None => {
+                            return Err(ShellSyntaxError {
+                                message: "unterminated double quote",
+                            });
+                        }
+                    }
+                    pos += 1;
+                }
+                pos += 1;
+            }
+            '$' if chars.get(pos) == Some(&'\'') => {
+                pos = ansi_c_string(&chars, pos + 1, word.get_or_insert_with(Vec::new))?;
+            }
+            c => push(&mut word, c),
+        }
+    }
+
+    if let Some(w) = word {
+        words.push(w);
+    }
+    if !words.is_empty() {
+        commands.push(words);
+    }
+
+    Ok(commands)
+}
+
+/// Decode the contents of a `$'...'` string starting at `pos`
+///
+/// Returns the position after the closing quote.
+fn ansi_c_string(chars: &[char], pos: usize, out: &mut Vec<u8>) -> Result<usize, ShellSyntaxError> {
+    let unterminated = || ShellSyntaxError {
+        message: "unterminated $' quote",
+    };
+
+    let mut pos = pos;
+
+    loop {
+        let c = *chars.get(pos).ok_or_else(unterminated)?;
+        pos += 1;
+
+        if c == '\'' {
+            return Ok(pos);
+        }
+
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        let c = *chars.get(pos).ok_or_else(unterminated)?;
+        pos += 1;
+
+        let byte = match c {
+            'a' => 0x07,
+            'b' => 0x08,
+            'e' | 'E' => 0x1b,
+            'f' => 0x0c,
+            'n' => b'\n',
+            'r' => b'\r',
+            't' => b'\t',
+            'v' => 0x0b,
+            'x' | '0'..='7' => {
+                let (radix, max_digits, start) = if c == 'x' {
+                    (16, 2, pos)
+                } else {
+                    (8, 3, pos - 1)
+                };
+
+                let digits: String = chars[start..]
+                    .iter()
+                    .take(max_digits)
+                    .take_while(|d| d.is_digit(radix))
+                    .collect();
+
+                if digits.is_empty() {
+                    out.extend_from_slice(b"\\x");
+                    continue;
+                }
+
+                pos = start + digits.len();
+                u32::from_str_radix(&digits, radix).unwrap_or(0) as u8
+            }
+            c => {
+                if !"\\'\"?".contains(c) {
+                    out.push(b'\\');
+                }
+                let mut buf = [0; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+        };
+
+        out.push(byte);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(input: &str) -> Vec<String> {
+        split_words(input)
+            .unwrap()
+            .into_iter()
+            .map(|w| String::from_utf8_lossy(&w).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            vec!["gcc", "-DMSG=hello world", "-c", "x.c"],
+            split("gcc -DMSG=\"hello world\"  -c x.c\n")
+        );
+        assert_eq!(
+            vec!["a b", "", "c'd", "$HOME"],
+            split("a\\ b '' \"c'd\" $HOME")
+        );
+        assert_eq!(
+            vec!["say \"hi\"", "\\n"],
+            split("\"say \\\"hi\\\"\" \"\\n\"")
+        );
+        assert_eq!(
+            vec!["gcc", "-c", "x.c"],
+            split("gcc \\\n    -c \\\n    x.c")
+        );
+        assert_eq!(vec!["ls"], split("ls # list\n"));
+        assert_eq!(
+            vec!["a\tb", "it's", "\u{1b}[0m", "A"],
+            split("$'a\\tb' $'it\\'s' $'\\e[0m' $'\\x41'")
+        );
+        assert_eq!(vec![b"\xff".to_vec()], split_words("$'\\xff'").unwrap());
+    }
+
+    #[test]
+    fn test_split_words_unterminated() {
+        assert!(split_words("echo 'abc").is_err());
+        assert!(split_words("echo \"abc").is_err());
+        assert!(split_words("echo $'abc").is_err());
+        assert!(split_words("echo abc\\").is_err());
+    }
+
+    #[test]
+    fn test_split_commands() {
+        let commands = split_commands("a \\\n  'b\nc'\n\n# comment\nd e # f\n").unwrap();
+
+        assert_eq!(
+            vec![
+                vec![b"a".to_vec(), b"b\nc".to_vec()],
+                vec![b"d".to_vec(), b"e".to_vec()]
+            ],
+            commands
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use error::MupsError;
+use maps::read_maps;
+use procfs::{strip_deleted, ProcFs};
+
+/// Why a file that a process runs is out of date
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Staleness {
+    /// The file is gone, the kernel marks it as deleted
+    Deleted,
+    /// Another file took its place, like after a package upgrade
+    Replaced,
+}
+
+impl fmt::Display for Staleness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Staleness::Deleted => write!(f, "deleted"),
+            Staleness::Replaced => write!(f, "replaced"),
+        }
+    }
+}
+
+/// An executable or shared library that is not the one on disk
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaleFile {
+    pub path: PathBuf,
+    /// Whether this is the executable of the process
+    pub exe: bool,
+    pub staleness: Staleness,
+}
+
+/// A process running an out of date executable or libraries
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaleProcess {
+    pub pid: u32,
+    pub files: Vec<StaleFile>,
+}
+
+/// Find the processes whose executable or shared libraries changed
+///
+/// A file counts as replaced when the file at its path, as seen from
+/// the root directory of the process, has another device or inode. That check
+/// is left out for processes whose root may not be looked at, so
+/// those are only found when the kernel marks the files as deleted.
+pub fn find_stale(procfs: &ProcFs) -> Result<Vec<StaleProcess>, MupsError> {
+    let mut stale = Vec::new();
+
+    for pid in procfs.pids()? {
+        match stale_files(procfs, pid) {
+            Ok(ref files) if files.is_empty() => {}
+            Ok(files) => stale.push(StaleProcess { pid, files }),
+            Err(MupsError::NoSuchProcess(_)) | Err(MupsError::PermissionDenied(_)) => {}
+            Err(e) => {
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(stale)
+}
+
+fn stale_files(procfs: &ProcFs, pid: u32) -> Result<Vec<StaleFile>, MupsError> {
+    /* Kernel threads have no executable, which makes them look like
+     * they are gone and so they are skipped */
+    let exe = procfs.read_link(pid, "exe")?;
+
+    let root = procfs.pid_path(pid, "root");
+    let root = if fs::metadata(&root).is_ok() {
+        Some(root.as_path())
+    } else {
+        None
+    };
+
+    let mut files = Vec::new();
+
+    let running_exe = fs::metadata(procfs.pid_path(pid, "exe")).ok();
+    let (exe, staleness) = check_file(&exe, running_exe.map(|m| (m.dev(), m.ino())), root);
+    if let Some(staleness) = staleness {
+        files.push(StaleFile {
+            path: exe.clone(),
+            exe: true,
+            staleness,
+        });
+    }
+
+    for region in read_maps(procfs, pid)? {
+        let path = match region.path {
+            Some(ref path) if is_shared_library(path) => path,
+            _ => {
+                continue;
+            }
+        };
+
+        let file = region.device().map(|dev| (dev, region.inode));
+        let (path, staleness) = check_file(path, file, root);
+        if let Some(staleness) = staleness {
+            if path != exe && !files.iter().any(|f| f.path == path) {
+                files.push(StaleFile {
+                    path,
+                    exe: false,
+                    staleness,
+                });
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Compare a file that a process uses with the file on disk
+///
+/// The file in use is given as its device and inode, if known. Returns
+/// the path without the deleted marker and what is wrong with the
+/// file, if anything.
+fn check_file(
+    path: &Path,
+    file: Option<(u64, u64)>,
+    root: Option<&Path>,
+) -> (PathBuf, Option<Staleness>) {
+    if let Some(path) = strip_deleted(path.as_os_str().as_bytes()) {
+        return (
+            PathBuf::from(OsStr::from_bytes(path)),
+            Some(Staleness::Deleted),
+        );
+    }
+
+    let (root, (dev, inode)) = match (root, file) {
+        (Some(root), Some(file)) => (root, file),
+        _ => {
+            return (path.to_path_buf(), None);
+        }
+    };
+
+    let on_disk = root.join(path.strip_prefix("/").unwrap_or(path));
+    let staleness = match fs::metadata(on_disk) {
+        Ok(ref metadata) if (metadata.dev(), metadata.ino()) != (dev, inode) => {
+            Some(Staleness::Replaced)
+        }
+        Ok(_) => None,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Some(Staleness::Deleted),
+        Err(_) => None,
+    };
+
+    (path.to_path_buf(), staleness)
+}
+
+/// Whether a path looks like `libfoo.so` or `libfoo.so.1.2`
+fn is_shared_library(path: &Path) -> bool {
+    match path.file_name() {
+        Some(name) => {
+            let name = name.to_string_lossy();
+            name.ends_with(".so") || name.contains(".so.")
+        }
+        None => false,
+    }
+}
+
+/// The systemd unit a process belongs to, from /proc/<pid>/cgroup
+///
+/// This is the innermost service or scope, so that a process in a
+/// user service is not lumped together with the user manager.
+pub fn service_of(procfs: &ProcFs, pid: u32) -> Option<String> {
+    let cgroup = procfs.read_pid_file(pid, "cgroup").ok()?;
+    let cgroup = String::from_utf8_lossy(&cgroup);
+
+    cgroup
+        .lines()
+        .rev()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .filter_map(|path| {
+            path.rsplit('/')
+                .find(|unit| unit.ends_with(".service") || unit.ends_with(".scope"))
+        })
+        .next()
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_shared_library() {
+        assert!(is_shared_library(Path::new("/usr/lib/libc.so.6")));
+        assert!(is_shared_library(Path::new("/opt/x/plugin.so")));
+        assert!(!is_shared_library(Path::new(
+            "/usr/lib/locale/locale-archive"
+        )));
+        assert!(!is_shared_library(Path::new("/usr/share/sonic.sounds")));
+    }
+
+    #[test]
+    fn test_check_file() {
+        assert_eq!(
+            (PathBuf::from("/usr/bin/x"), Some(Staleness::Deleted)),
+            check_file(Path::new("/usr/bin/x (deleted)"), None, None)
+        );
+        assert_eq!(
+            (PathBuf::from("/usr/bin/x"), None),
+            check_file(Path::new("/usr/bin/x"), Some((1, 1)), None)
+        );
+
+        /* The same inode number on another filesystem is another file */
+        let metadata = fs::metadata("/").unwrap();
+        assert_eq!(
+            (PathBuf::from("/"), None),
+            check_file(
+                Path::new("/"),
+                Some((metadata.dev(), metadata.ino())),
+                Some(Path::new("/"))
+            )
+        );
+        assert_eq!(
+            (PathBuf::from("/"), Some(Staleness::Replaced)),
+            check_file(
+                Path::new("/"),
+                Some((metadata.dev() + 1, metadata.ino())),
+                Some(Path::new("/"))
+            )
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::path::PathBuf;
+
+use error::MupsError;
+use procfs::{bytes_from_path, ProcFs};
+
+/// Contents of /proc/<pid>/stat, as documented in proc(5)
+///
+/// The fields are listed in the same order as they appear in the
+/// file. The ones marked as optional were added in later kernel
+/// versions and are `None` when the running kernel does not provide
+/// them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcStat {
+    pub pid: u32,
+    pub comm: String,
+    pub state: char,
+    pub ppid: u32,
+    pub pgrp: i32,
+    pub session: i32,
+    pub tty_nr: i32,
+    pub tpgid: i32,
+    pub flags: u32,
+    pub minflt: u64,
+    pub cminflt: u64,
+    pub majflt: u64,
+    pub cmajflt: u64,
+    pub utime: u64,
+    pub stime: u64,
+    pub cutime: i64,
+    pub cstime: i64,
+    pub priority: i64,
+    pub nice: i64,
+    pub num_threads: i64,
+    pub itrealvalue: i64,
+    pub starttime: u64,
+    pub vsize: u64,
+    pub rss: i64,
+    pub rsslim: u64,
+    pub startcode: u64,
+    pub endcode: u64,
+    pub startstack: u64,
+    pub kstkesp: u64,
+    pub kstkeip: u64,
+    pub signal: u64,
+    pub blocked: u64,
+    pub sigignore: u64,
+    pub sigcatch: u64,
+    pub wchan: u64,
+    pub nswap: u64,
+    pub cnswap: u64,
+    pub exit_signal: i32,
+    pub processor: i32,
+    pub rt_priority: u32,
+    pub policy: u32,
+    pub delayacct_blkio_ticks: u64,
+    pub guest_time: u64,
+    pub cguest_time: i64,
+    pub start_data: Option<u64>,
+    pub end_data: Option<u64>,
+    pub start_brk: Option<u64>,
+    pub arg_start: Option<u64>,
+    pub arg_end: Option<u64>,
+    pub env_start: Option<u64>,
+    pub env_end: Option<u64>,
+    pub exit_code: Option<i32>,
+}
+
+impl ProcStat {
+    /// Parse the contents of a stat file
+    ///
+    /// The comm field may contain any characters, including spaces
+    /// and parentheses, so it is taken to extend from the first '('
+    /// to the last ')' in the record. It need not be valid UTF-8
+    /// either, since a process can name itself anything, so invalid
+    /// sequences in it are replaced with U+FFFD.
+    pub fn parse(stat: &[u8]) -> Option<ProcStat> {
+        use std::str::FromStr;
+
+        fn field<'a, T, I>(pieces: &mut I) -> Option<T>
+        where
+            T: FromStr,
+            I: Iterator<Item = &'a str>,
+        {
+            match pieces.next() {
+                Some(s) => s.parse::<T>().ok(),
+                None => None,
+            }
+        }
+
+        fn opt_field<'a, T, I>(pieces: &mut I) -> Option<Option<T>>
+        where
+            T: FromStr,
+            I: Iterator<Item = &'a str>,
+        {
+            match pieces.next() {
+                Some(s) => s.parse::<T>().ok().map(Some),
+                None => Some(None),
+            }
+        }
+
+        let lparen = stat.iter().position(|&c| c == b'(')?;
+        let rparen = stat.iter().rposition(|&c| c == b')')?;
+
+        if rparen < lparen {
+            return None;
+        }
+
+        let pid = ::std::str::from_utf8(&stat[..lparen])
+            .ok()?
+            .trim()
+            .parse::<u32>()
+            .ok()?;
+        let comm = String::from_utf8_lossy(&stat[(lparen + 1)..rparen]);
+
+        let rest = ::std::str::from_utf8(&stat[(rparen + 1)..]).ok()?;
+        let mut pieces = rest.split_whitespace();
+
+        let state = {
+            let s = pieces.next()?;
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => {
+                    return None;
+                }
+            }
+        };
+
+        let p = &mut pieces;
+
+        Some(ProcStat {
+            pid,
+            comm: comm.into_owned(),
+            state,
+            ppid: field(p)?,
+            pgrp: field(p)?,
+            session: field(p)?,
+            tty_nr: field(p)?,
+            tpgid: field(p)?,
+            flags: field(p)?,
+            minflt: field(p)?,
+            cminflt: field(p)?,
+            majflt: field(p)?,
+            cmajflt: field(p)?,
+            utime: field(p)?,
+            stime: field(p)?,
+            cutime: field(p)?,
+            cstime: field(p)?,
+            priority: field(p)?,
+            nice: field(p)?,
+            num_threads: field(p)?,
+            itrealvalue: field(p)?,
+            starttime: field(p)?,
+            vsize: field(p)?,
+            rss: field(p)?,
+            rsslim: field(p)?,
+            startcode: field(p)?,
+            endcode: field(p)?,
+            startstack: field(p)?,
+            kstkesp: field(p)?,
+            kstkeip: field(p)?,
+            signal: field(p)?,
+            blocked: field(p)?,
+            sigignore: field(p)?,
+            sigcatch: field(p)?,
+            wchan: field(p)?,
+            nswap: field(p)?,
+            cnswap: field(p)?,
+            exit_signal: field(p)?,
+            processor: field(p)?,
+            rt_priority: field(p)?,
+            policy: field(p)?,
+            delayacct_blkio_ticks: field(p)?,
+            guest_time: field(p)?,
+            cguest_time: field(p)?,
+            start_data: opt_field(p)?,
+            end_data: opt_field(p)?,
+            start_brk: opt_field(p)?,
+            arg_start: opt_field(p)?,
+            arg_end: opt_field(p)?,
+            env_start: opt_field(p)?,
+            env_end: opt_field(p)?,
+            exit_code: opt_field(p)?,
+        })
+    }
+
+    /// Read and parse /proc/<pid>/stat
+    pub fn read_pid(procfs: &ProcFs, pid: u32) -> Result<ProcStat, MupsError> {
+        ProcStat::read_path(procfs.pid_path(pid, "stat"), pid)
+    }
+
+    /// Read and parse /proc/<pid>/task/<tid>/stat of a single thread
+    pub fn read_tid(procfs: &ProcFs, pid: u32, tid: u32) -> Result<ProcStat, MupsError> {
+        ProcStat::read_path(procfs.pid_path(pid, &format!("task/{}/stat", tid)), pid)
+    }
+
+    fn read_path(path: PathBuf, pid: u32) -> Result<ProcStat, MupsError> {
+        let stat = bytes_from_path(&path).map_err(|e| e.for_pid(pid))?;
+
+        match ProcStat::parse(&stat) {
+            Some(s) => Ok(s),
+            None => Err(MupsError::MalformedProcFile {
+                path,
+                content: String::from_utf8_lossy(&stat).into_owned(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_procstat_parse_1() {
+        let stat = ProcStat::parse(include_bytes!("td/stat_1.txt")).unwrap();
+
+        assert_eq!(4242, stat.pid);
+        assert_eq!("evil) (comm x", &stat.comm);
+        assert_eq!('S', stat.state);
+        assert_eq!(1540, stat.ppid);
+        assert_eq!(34816, stat.tty_nr);
+        assert_eq!(15, stat.utime);
+        assert_eq!(-5, stat.nice);
+        assert_eq!(3, stat.num_threads);
+        assert_eq!(51580, stat.starttime);
+        assert_eq!(314, stat.rss);
+        assert_eq!(2, stat.processor);
+        assert_eq!(Some(0), stat.exit_code);
+    }
+
+    #[test]
+    fn test_procstat_parse_truncated() {
+        assert_eq!(None, ProcStat::parse(b"4242 (foo) S"));
+        assert_eq!(None, ProcStat::parse(b"4242 (foo S 1 2 3"));
+        assert_eq!(None, ProcStat::parse(b"4242 (foo) S x"));
+    }
+
+    #[test]
+    fn test_procstat_parse_invalid_utf8() {
+        let stat = ProcStat::parse(
+            b"5 (caf\xe9) S 1 5 5 0 -1 4194560 0 0 0 0 0 0 0 0 20 0 1 0 100 0 0 \
+              18446744073709551615 0 0 0 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0",
+        )
+        .unwrap();
+
+        assert_eq!(5, stat.pid);
+        assert_eq!("caf\u{fffd}", &stat.comm);
+        assert_eq!(1, stat.ppid);
+    }
+}
@@ -0,0 +1,64 @@
+use error::MupsError;
+use procfs::{string_from_path, ProcFs};
+
+/// Contents of /proc/<pid>/status
+///
+/// The file has one `Name:\tvalue` pair per line. Only the fields
+/// that mups has a use for get their own accessors, the rest can be
+/// looked up by name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcStatus {
+    fields: Vec<(String, String)>,
+}
+
+impl ProcStatus {
+    pub fn parse(status: &str) -> ProcStatus {
+        let fields = status
+            .lines()
+            .filter_map(|line| {
+                let colon = line.find(':')?;
+                Some((
+                    String::from(&line[..colon]),
+                    String::from(line[(colon + 1)..].trim()),
+                ))
+            })
+            .collect();
+
+        ProcStatus { fields }
+    }
+
+    /// Read and parse /proc/<pid>/status
+    pub fn read_pid(procfs: &ProcFs, pid: u32) -> Result<ProcStatus, MupsError> {
+        let path = procfs.pid_path(pid, "status");
+        let status = string_from_path(&path).map_err(|e| e.for_pid(pid))?;
+
+        Ok(ProcStatus::parse(&status))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|f| f.0 == name)
+            .map(|f| f.1.as_str())
+    }
+
+    /// Real user id of the process
+    pub fn uid(&self) -> Option<u32> {
+        self.get("Uid")?.split_whitespace().next()?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_parse() {
+        let status = ProcStatus::parse("Name:\tcat\nUmask:\t0022\nUid:\t1000\t1000\t0\t1000\n");
+
+        assert_eq!(Some("cat"), status.get("Name"));
+        assert_eq!(Some("0022"), status.get("Umask"));
+        assert_eq!(Some(1000), status.uid());
+        assert_eq!(None, status.get("Gid"));
+    }
+}
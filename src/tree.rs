@@ -0,0 +1,323 @@
+use std::collections::{BTreeMap, HashMap};
+
+use error::MupsError;
+use format::{format_args, ArgFormat};
+use json::{process_json, Json};
+use procfs::ProcFs;
+use stat::ProcStat;
+
+/// Parent and child relations between processes
+///
+/// The tree is built from a single pass over the stat files of all
+/// processes, so processes that come and go during that pass may or
+/// may not be included.
+#[derive(Clone, Debug)]
+pub struct ProcessTree {
+    stats: BTreeMap<u32, ProcStat>,
+    children: BTreeMap<u32, Vec<u32>>,
+}
+
+/// How `ProcessTree::render` should draw the tree
+#[derive(Clone, Debug, Default)]
+pub struct TreeOptions {
+    /// Use plain ASCII instead of box drawing characters
+    pub ascii: bool,
+    /// Show identical sibling subtrees once, as `4*[nginx]`
+    pub collapse: bool,
+    /// How many levels below the root to show, if not all of them
+    pub max_depth: Option<usize>,
+    /// Show the arguments of each process instead of its name
+    pub show_args: bool,
+    /// How to lay out the arguments, with `show_args`
+    pub arg_format: ArgFormat,
+}
+
+impl ProcessTree {
+    pub fn from_stats<I: IntoIterator<Item = ProcStat>>(stats: I) -> ProcessTree {
+        let stats: BTreeMap<u32, ProcStat> = stats.into_iter().map(|s| (s.pid, s)).collect();
+        let mut children: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+
+        for stat in stats.values() {
+            if stat.ppid != stat.pid {
+                children.entry(stat.ppid).or_default().push(stat.pid);
+            }
+        }
+
+        ProcessTree { stats, children }
+    }
+
+    /// Read the stat files of all processes
+    pub fn read(procfs: &ProcFs) -> Result<ProcessTree, MupsError> {
+        let mut stats = Vec::new();
+
+        for pid in procfs.pids()? {
+            match ProcStat::read_pid(procfs, pid) {
+                Ok(stat) => stats.push(stat),
+                Err(MupsError::NoSuchProcess(_)) => {}
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(ProcessTree::from_stats(stats))
+    }
+
+    /// Direct children of a process, in order of pid
+    pub fn children(&self, pid: u32) -> &[u32] {
+        match self.children.get(&pid) {
+            Some(c) => c,
+            None => &[],
+        }
+    }
+
+    /// All processes below `pid`, parents before their children
+    pub fn descendants(&self, pid: u32) -> Vec<u32> {
+        let mut descendants = Vec::new();
+        let mut stack: Vec<u32> = self.children(pid).iter().rev().cloned().collect();
+
+        while let Some(pid) = stack.pop() {
+            /* A snapshot taken while processes came and went may
+             * contain a loop, which we must not follow forever. */
+            if descendants.contains(&pid) {
+                continue;
+            }
+
+            descendants.push(pid);
+            stack.extend(self.children(pid).iter().rev());
+        }
+
+        descendants
+    }
+
+    pub fn get(&self, pid: u32) -> Option<&ProcStat> {
+        self.stats.get(&pid)
+    }
+
+    /// Describe the subtree starting from `root` as nested objects
+    ///
+    /// Each process is described as by `process_json`, with its
+    /// children in a `children` array.
+    pub fn to_json(
+        &self,
+        procfs: &ProcFs,
+        root: u32,
+        max_depth: Option<usize>,
+    ) -> Result<Json, MupsError> {
+        if !self.stats.contains_key(&root) {
+            return Err(MupsError::NoSuchProcess(root));
+        }
+
+        self.subtree_json(procfs, root, max_depth, &mut Vec::new())
+    }
+
+    /// `to_json` for a process below the processes in `path`
+    fn subtree_json(
+        &self,
+        procfs: &ProcFs,
+        pid: u32,
+        max_depth: Option<usize>,
+        path: &mut Vec<u32>,
+    ) -> Result<Json, MupsError> {
+        let mut children = Vec::new();
+
+        path.push(pid);
+
+        if max_depth != Some(0) {
+            /* Like in `descendants`, a loop must not be followed */
+            for &child in self.children(pid) {
+                if !path.contains(&child) {
+                    children.push(self.subtree_json(
+                        procfs,
+                        child,
+                        max_depth.map(|d| d - 1),
+                        path,
+                    )?);
+                }
+            }
+        }
+
+        path.pop();
+
+        Ok(process_json(procfs, pid)?.field("children", children))
+    }
+
+    /// Draw the subtree starting from `root`
+    ///
+    /// With `show_args`, the arguments of the processes are read from
+    /// `procfs` as the tree is drawn.
+    pub fn render(
+        &self,
+        procfs: &ProcFs,
+        root: u32,
+        options: &TreeOptions,
+    ) -> Result<String, MupsError> {
+        if !self.stats.contains_key(&root) {
+            return Err(MupsError::NoSuchProcess(root));
+        }
+
+        let mut renderer = Renderer {
+            tree: self,
+            procfs,
+            options,
+            labels: HashMap::new(),
+            path: Vec::new(),
+            out: String::new(),
+        };
+
+        renderer.group(&[root], "", "", 0);
+
+        Ok(renderer.out)
+    }
+}
+
+struct Renderer<'a> {
+    tree: &'a ProcessTree,
+    procfs: &'a ProcFs,
+    options: &'a TreeOptions,
+    labels: HashMap<u32, String>,
+    /// The processes from the root down to the one being drawn
+    path: Vec<u32>,
+    out: String,
+}
+
+impl<'a> Renderer<'a> {
+    /// Draw a group of identical subtrees, represented by the first
+    fn group(&mut self, pids: &[u32], first_prefix: &str, rest_prefix: &str, depth: usize) {
+        let (branch, last_branch, guide, last_guide) = if self.options.ascii {
+            ("|- ", "`- ", "|  ", "   ")
+        } else {
+            ("├─ ", "└─ ", "│  ", "   ")
+        };
+
+        let pid = pids[0];
+        self.path.push(pid);
+
+        let groups = if self.shows_children(depth) {
+            let children = self.children(pid);
+            self.group_children(&children, depth + 1)
+        } else {
+            Vec::new()
+        };
+
+        let label = if pids.len() > 1 {
+            format!("{}*[{}]", pids.len(), self.label(pid))
+        } else {
+            format!("{} {}", pid, self.label(pid))
+        };
+
+        let continuation = if groups.is_empty() { "   " } else { guide };
+
+        for (n, line) in label.lines().enumerate() {
+            if n == 0 {
+                self.out.push_str(first_prefix);
+            } else {
+                self.out.push_str(rest_prefix);
+                self.out.push_str(continuation);
+            }
+            self.out.push_str(line);
+            self.out.push('\n');
+        }
+
+        for (n, group) in groups.iter().enumerate() {
+            let last = n + 1 == groups.len();
+            let child_first = format!("{}{}", rest_prefix, if last { last_branch } else { branch });
+            let child_rest = format!("{}{}", rest_prefix, if last { last_guide } else { guide });
+
+            self.group(group, &child_first, &child_rest, depth + 1);
+        }
+
+        self.path.pop();
+    }
+
+    /// Children of a process that are not also above it
+    ///
+    /// Like in `descendants`, a loop must not be followed forever.
+    fn children(&self, pid: u32) -> Vec<u32> {
+        self.tree
+            .children(pid)
+            .iter()
+            .filter(|c| !self.path.contains(c))
+            .cloned()
+            .collect()
+    }
+
+    /// Split children into groups of identical subtrees
+    ///
+    /// Without collapsing, every child is a group of its own.
+    fn group_children(&mut self, children: &[u32], depth: usize) -> Vec<Vec<u32>> {
+        let mut groups: Vec<(String, Vec<u32>)> = Vec::new();
+
+        for &child in children {
+            if !self.options.collapse {
+                groups.push((String::new(), vec![child]));
+                continue;
+            }
+
+            let signature = self.signature(child, depth);
+
+            match groups.iter_mut().find(|g| g.0 == signature) {
+                Some(g) => g.1.push(child),
+                None => groups.push((signature, vec![child])),
+            }
+        }
+
+        groups.into_iter().map(|g| g.1).collect()
+    }
+
+    fn label(&mut self, pid: u32) -> String {
+        if let Some(label) = self.labels.get(&pid) {
+            return label.clone();
+        }
+
+        let comm = match self.tree.get(pid) {
+            Some(stat) => stat.comm.clone(),
+            None => String::from("?"),
+        };
+
+        let label = if self.options.show_args {
+            let args = self.procfs.read_cmdline(pid).unwrap_or_default();
+
+            if args.is_empty() {
+                format!("[{}]", comm)
+            } else {
+                String::from_utf8_lossy(&format_args(&args, &self.options.arg_format)).into_owned()
+            }
+        } else {
+            comm
+        };
+
+        self.labels.insert(pid, label.clone());
+        label
+    }
+
+    /// Whether the children of a process at `depth` are shown
+    fn shows_children(&self, depth: usize) -> bool {
+        match self.options.max_depth {
+            Some(max_depth) => depth < max_depth,
+            None => true,
+        }
+    }
+
+    /// Description of a subtree, without pids, as far as it is shown
+    fn signature(&mut self, pid: u32, depth: usize) -> String {
+        let mut signature = self.label(pid);
+
+        if self.shows_children(depth) {
+            self.path.push(pid);
+            let children = self.children(pid);
+            let mut child_signatures: Vec<String> = children
+                .iter()
+                .map(|c| self.signature(*c, depth + 1))
+                .collect();
+            child_signatures.sort();
+            self.path.pop();
+
+            signature.push('(');
+            signature.push_str(&child_signatures.join(","));
+            signature.push(')');
+        }
+
+        signature
+    }
+}
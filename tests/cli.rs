@@ -0,0 +1,1206 @@
+extern crate mups;
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::{symlink, MetadataExt};
+
+use common::{run_with_closed_stdout, run_with_stdin, FakeProc};
+
+#[test]
+fn test_args() {
+    let fake = FakeProc::new();
+    fake.process(42).cmdline(&["gcc", "-c", "hello world.c"]);
+
+    let out = fake.run(&["args", "-p", "42"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "gcc \\\n    -c \\\n    'hello world.c'\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_quoting() {
+    let fake = FakeProc::new();
+    fake.process(42)
+        .file("cmdline", b"sh\0-c\0echo \"$HOME\" it's\n\0caf\xe9\0");
+
+    let out = fake.run(&["args", "-p", "42"]);
+    assert_eq!(
+        &b"sh \\\n    -c \\\n    'echo \"$HOME\" it'\\''s\n' \\\n    'caf\xe9'\n"[..],
+        &out.stdout[..]
+    );
+
+    let out = fake.run(&["args", "-p", "42", "--quoting", "bash"]);
+    assert_eq!(
+        "sh \\\n    -c \\\n    $'echo \"$HOME\" it\\'s\\n' \\\n    $'caf\\xe9'\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["args", "-p", "42", "--quoting", "none"]);
+    assert_eq!(
+        &b"sh \\\n    -c \\\n    echo \"$HOME\" it's\n \\\n    caf\xe9\n"[..],
+        &out.stdout[..]
+    );
+}
+
+#[test]
+fn test_args_grouping() {
+    let fake = FakeProc::new();
+    fake.process(42)
+        .cmdline(&["/usr/bin/gcc", "-c", "-o", "x.o", "-I", "inc", "x.c"]);
+
+    let out = fake.run(&["args", "-p", "42"]);
+    assert_eq!(
+        "/usr/bin/gcc \\\n    -c \\\n    -o x.o \\\n    -I inc \\\n    x.c\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["args", "-p", "42", "--no-group"]);
+    assert_eq!(
+        "/usr/bin/gcc \\\n    -c \\\n    -o \\\n    x.o \\\n    -I \\\n    inc \\\n    x.c\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_profile() {
+    let fake = FakeProc::new();
+    fake.process(42)
+        .cmdline(&["./build-tool", "-DB", "-I", "inc", "-DA", "x.c"]);
+
+    let out = fake.run(&["args", "-p", "42"]);
+    assert_eq!(
+        "./build-tool \\\n    -DB \\\n    -I \\\n    inc \\\n    -DA \\\n    x.c\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["args", "-p", "42", "--profile", "compiler"]);
+    assert_eq!(
+        "./build-tool \\\n    -DA \\\n    -DB \\\n    -I inc \\\n    x.c\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_response_files() {
+    let fake = FakeProc::new();
+    let work = fake.root().join("work");
+    fs::create_dir_all(&work).unwrap();
+    fs::write(work.join("flags.rsp"), "-I 'inc dir'\n-DX=1 @more.rsp\n").unwrap();
+    fs::write(work.join("more.rsp"), "-Wall").unwrap();
+    fake.process(42)
+        .cmdline(&["gcc", "-c", "@flags.rsp", "x.c", "@missing.rsp"])
+        .cwd(work.to_str().unwrap());
+
+    let out = fake.run(&["args", "-p", "42", "--expand-response-files"]);
+    assert_eq!(
+        format!(
+            "# @flags.rsp: {0}/flags.rsp\n\
+             #   @more.rsp: {0}/more.rsp\n\
+             gcc \\\n    -c \\\n        -I 'inc dir' \\\n        -DX=1 \\\n            -Wall \\\n    \
+             x.c \\\n    @missing.rsp\n",
+            work.display()
+        ),
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&[
+        "args",
+        "-p",
+        "42",
+        "--expand-response-files",
+        "--format",
+        "ndjson",
+    ]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains(r#""response_files":[{"arg":"@flags.rsp","#));
+    assert!(stdout.contains(r#""argv":["-I","inc dir","-DX=1","-Wall"]"#));
+}
+
+#[test]
+fn test_args_response_files_order() {
+    let fake = FakeProc::new();
+    let work = fake.root().join("work");
+    fs::create_dir_all(&work).unwrap();
+    fs::write(work.join("undef_a.rsp"), "-UA").unwrap();
+    fake.process(42)
+        .cmdline(&["gcc", "-DB", "@undef_a.rsp", "-DA", "-c", "x.c"])
+        .cwd(work.to_str().unwrap());
+
+    let out = fake.run(&["args", "-p", "42"]);
+    assert_eq!(
+        "gcc \\\n    -DB \\\n    @undef_a.rsp \\\n    -DA \\\n    -c \\\n    x.c\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_response_files_loop() {
+    let fake = FakeProc::new();
+    let work = fake.root().join("work");
+    fs::create_dir_all(&work).unwrap();
+    fs::write(work.join("a.rsp"), "@a.rsp @a.rsp").unwrap();
+    fake.process(42)
+        .cmdline(&["gcc", "@a.rsp"])
+        .cwd(work.to_str().unwrap());
+
+    let out = fake.run(&["args", "-p", "42", "--expand-response-files"]);
+    assert!(out.status.success());
+    assert_eq!(
+        format!(
+            "# @a.rsp: {0}/a.rsp\n\
+             gcc \\\n        @a.rsp \\\n        @a.rsp\n",
+            work.display()
+        ),
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_response_files_not_read() {
+    let fake = FakeProc::new();
+    let work = fake.root().join("work");
+    fs::create_dir_all(work.join("dir.rsp")).unwrap();
+    symlink("/dev/zero", work.join("zero.rsp")).unwrap();
+    fs::write(work.join("huge.rsp"), vec![b'x'; 5 << 20]).unwrap();
+    fake.process(42)
+        .cmdline(&["gcc", "@dir.rsp", "@zero.rsp", "@huge.rsp"])
+        .cwd(work.to_str().unwrap());
+
+    let out = fake.run(&["args", "-p", "42", "--expand-response-files"]);
+    assert!(out.status.success());
+    assert_eq!(
+        "gcc \\\n    @dir.rsp \\\n    @zero.rsp \\\n    @huge.rsp\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_response_files_other_programs() {
+    let fake = FakeProc::new();
+    let work = fake.root().join("work");
+    fs::create_dir_all(&work).unwrap();
+    fs::write(work.join("body.json"), "{}").unwrap();
+    fake.process(42)
+        .cmdline(&["curl", "-d", "@body.json", "localhost"])
+        .cwd(work.to_str().unwrap());
+
+    let out = fake.run(&["args", "-p", "42", "--expand-response-files"]);
+    assert_eq!(
+        "curl \\\n    -d \\\n    @body.json \\\n    localhost\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&[
+        "args",
+        "-p",
+        "42",
+        "--expand-response-files",
+        "--profile",
+        "compiler",
+    ]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("# @body.json: "));
+}
+
+#[test]
+fn test_args_diff() {
+    let fake = FakeProc::new();
+    fake.process(42)
+        .cmdline(&["java", "-Xmx1g", "-jar", "app.jar", "--port", "80"]);
+    fake.process(43)
+        .cmdline(&["java", "-Xmx2g", "-jar", "app.jar", "--port", "80", "-v"]);
+
+    let out = fake.run(&["args", "--diff", "42", "43"]);
+    assert_eq!(
+        " java \\\n-    -Xmx1g \\\n+    -Xmx2g \\\n     -jar app.jar \\\n     --port 80\n+    -v\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["args", "--diff", "42", "43", "-p", "1"]);
+    assert_eq!(Some(1), out.status.code());
+}
+
+#[test]
+fn test_args_diff_json() {
+    let fake = FakeProc::new();
+    fake.process(42).file("cmdline", b"cat\0caf\xe9\0");
+    fake.process(43).cmdline(&["cat"]);
+
+    let out = fake.run(&["args", "--diff", "42", "43", "--format", "ndjson"]);
+    assert_eq!(
+        "{\"pid_a\":42,\"pid_b\":43,\"changes\":[{\"op\":\"same\",\"arg\":\"cat\"},\
+         {\"op\":\"removed\",\"arg\":\"caf\u{fffd}\",\"arg_base64\":\"Y2Fm6Q==\"}]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_no_such_process() {
+    let fake = FakeProc::new();
+
+    let out = fake.run(&["args", "-p", "42"]);
+
+    assert_eq!(Some(3), out.status.code());
+    assert_eq!(
+        "mups: no process with pid 42\n",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[test]
+fn test_whatps() {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init").cmdline(&["/sbin/init"]);
+    fake.process(100).comm("sh").ppid(1).cmdline(&["sh"]);
+    fake.process(200)
+        .comm("make")
+        .ppid(100)
+        .state('R')
+        .cmdline(&["make", "-j4"]);
+
+    let out = fake.run(&["whatps", "-p", "200"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "\npid 1 [S]:\n/sbin/init\n\
+         \npid 100 [S]:\nsh\n\
+         \npid 200 [R]:\nmake \\\n    -j4\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_whatps_exited_midway() {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init").cmdline(&["/sbin/init"]);
+    fake.process(100).comm("sh").ppid(1).cmdline(&["sh"]);
+    fake.process(200).comm("make").ppid(100).cmdline(&["make"]);
+    fake.remove(100);
+
+    let out = fake.run(&["whatps", "-p", "200"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "\npid 100 [?]:\n(process has exited)\n\npid 200 [S]:\nmake\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+fn nginx_tree() -> FakeProc {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init").cmdline(&["/sbin/init"]);
+    fake.process(2).comm("kthreadd").ppid(0);
+    fake.process(100)
+        .comm("nginx")
+        .cmdline(&["nginx", "-g", "daemon off;"]);
+    for pid in 101..105 {
+        fake.process(pid)
+            .comm("nginx")
+            .ppid(100)
+            .cmdline(&["nginx: worker process"]);
+    }
+    fake.process(110).comm("sh").cmdline(&["sh"]);
+    fake.process(111)
+        .comm("sleep")
+        .ppid(110)
+        .cmdline(&["sleep", "60"]);
+    fake
+}
+
+#[test]
+fn test_repro() {
+    let fake = FakeProc::new();
+    let process = fake
+        .process(42)
+        .comm("python3")
+        .cmdline(&["python3", "-m", "http.server", "--bind", "::1"])
+        .cwd("/srv/www")
+        .exe("/usr/bin/python3.11")
+        .environ(&[
+            "PATH=/usr/bin:/bin",
+            "MSG=hello world",
+            "BASH_FUNC_f%%=() { :; }",
+        ]);
+    process.file(
+        "limits",
+        b"Limit                     Soft Limit           Hard Limit           Units     \n\
+          Max stack size            8388608              unlimited            bytes     \n\
+          Max open files            1024                 1024                 files     \n",
+    );
+
+    let out = fake.run(&["repro", "-p", "42"]);
+    assert_eq!(
+        "#!/bin/bash\n\
+         # Recreated from process 42: python3\n\
+         cd /srv/www || exit 1\n\
+         umask 0022\n\
+         ulimit -H -s unlimited\n\
+         ulimit -S -s 8192\n\
+         ulimit -n 1024\n\
+         for var in $(compgen -e); do unset \"$var\" 2>/dev/null; done\n\
+         export PATH=/usr/bin:/bin\n\
+         export MSG='hello world'\n\
+         # can not set: 'BASH_FUNC_f%%=() { :; }'\n\
+         exec -a python3 /usr/bin/python3.11 \\\n    -m \\\n    http.server \\\n    --bind ::1\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["repro", "-p", "42", "--quoting", "none"]);
+    assert_eq!(Some(1), out.status.code());
+    assert!(out.stdout.is_empty());
+}
+
+#[test]
+fn test_stale() {
+    let fake = FakeProc::new();
+    let fsroot = fake.root().join("fsroot");
+    fs::create_dir_all(fsroot.join("usr/lib")).unwrap();
+    fs::write(fsroot.join("usr/lib/libnew.so.1"), b"").unwrap();
+    fs::write(fsroot.join("usr/lib/libsame.so.1"), b"").unwrap();
+    fs::write(fsroot.join("usr/lib/libmoved.so.1"), b"").unwrap();
+    let same = fs::metadata(fsroot.join("usr/lib/libsame.so.1")).unwrap();
+    let moved = fs::metadata(fsroot.join("usr/lib/libmoved.so.1")).unwrap();
+    let major = ((same.dev() >> 8) & 0xfff) | ((same.dev() >> 32) & !0xfff);
+    let minor = (same.dev() & 0xff) | ((same.dev() >> 12) & !0xff);
+    let maps = format!(
+        "55d5c4a00000-55d5c4a08000 r-xp 00000000 fd:01 1 /usr/bin/app (deleted)\n\
+         7f1c2a200000-7f1c2a400000 r-xp 00000000 fd:01 2 /usr/lib/libnew.so.1\n\
+         7f1c2a400000-7f1c2a600000 r-xp 00000000 {major:x}:{minor:x} {} /usr/lib/libsame.so.1\n\
+         7f1c2a600000-7f1c2a800000 r-xp 00000000 {other:x}:{minor:x} {} /usr/lib/libmoved.so.1\n\
+         7f1c2a800000-7f1c2aa00000 r-xp 00000000 fd:01 3 /usr/lib/libgone.so\n",
+        same.ino(),
+        moved.ino(),
+        major = major,
+        minor = minor,
+        other = major + 1
+    );
+
+    fake.process(1).comm("init").cmdline(&["/sbin/init"]);
+    let app = fake
+        .process(42)
+        .comm("app")
+        .ppid(1)
+        .exe("/usr/bin/app (deleted)");
+    app.file("maps", maps.as_bytes());
+    app.file("cgroup", b"0::/system.slice/app.service\n");
+    symlink(&fsroot, fake.root().join("42/root")).unwrap();
+    let sh = fake.process(43).comm("sh").ppid(1).exe("/bin/sh (deleted)");
+    sh.file("maps", b"");
+
+    let out = fake.run(&["stale"]);
+    assert_eq!(
+        "app.service:\n\
+         \x20 pid 42 (app): exe /usr/bin/app (deleted)\n\
+         \x20 pid 42 (app): /usr/lib/libnew.so.1 (replaced)\n\
+         \x20 pid 42 (app): /usr/lib/libmoved.so.1 (replaced)\n\
+         \x20 pid 42 (app): /usr/lib/libgone.so (deleted)\n\
+         \n\
+         parent 1 (init):\n\
+         \x20 pid 43 (sh): exe /bin/sh (deleted)\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["stale", "--format", "ndjson"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains(
+        r#"{"pid":43,"comm":"sh","group":"parent 1 (init)","files":[{"path":"/bin/sh","exe":true,"staleness":"deleted"}]}"#
+    ));
+}
+
+#[test]
+fn test_invalid_utf8_comm() {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init").cmdline(&["/sbin/init"]);
+    fake.process(5).file(
+        "stat",
+        b"5 (caf\xe9) S 1 5 5 0 -1 4194560 0 0 0 0 0 0 0 0 20 0 1 0 100 0 0 \
+          18446744073709551615 0 0 0 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0\n",
+    );
+
+    let out = fake.run(&["tree"]);
+    assert!(out.status.success());
+    assert_eq!(
+        "1 init\n└─ 5 caf\u{fffd}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["args", "--name", "init"]);
+    assert!(out.status.success());
+    assert_eq!("/sbin/init\n", String::from_utf8_lossy(&out.stdout));
+}
+
+#[test]
+fn test_tree() {
+    let fake = nginx_tree();
+
+    let out = fake.run(&["tree"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "1 init\n\
+         ├─ 100 nginx\n\
+         │  ├─ 101 nginx\n\
+         │  ├─ 102 nginx\n\
+         │  ├─ 103 nginx\n\
+         │  └─ 104 nginx\n\
+         └─ 110 sh\n\
+         \x20  └─ 111 sleep\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_tree_collapse_ascii() {
+    let fake = nginx_tree();
+
+    let out = fake.run(&["tree", "--collapse", "--ascii", "-p", "100"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "100 nginx\n`- 4*[nginx]\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_tree_depth_args() {
+    let fake = nginx_tree();
+
+    let out = fake.run(&["tree", "--depth", "1", "--args"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "1 /sbin/init\n\
+         ├─ 100 nginx \\\n\
+         │         -g \\\n\
+         │         'daemon off;'\n\
+         └─ 110 sh\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_tree_quoting() {
+    let fake = FakeProc::new();
+    fake.process(20).comm("echo").cmdline(&["echo", "a\tb"]);
+
+    let out = fake.run(&["tree", "-p", "20", "--args", "--quoting", "bash"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "20 echo \\\n       $'a\\tb'\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["tree", "-p", "20", "--args", "--quoting", "none"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "20 echo \\\n       a\tb\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_tree_no_group() {
+    let fake = FakeProc::new();
+    fake.process(20)
+        .comm("gcc")
+        .cmdline(&["gcc", "-o", "x", "x.c"]);
+
+    let out = fake.run(&["tree", "-p", "20", "--args"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "20 gcc \\\n       -o x \\\n       x.c\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["tree", "-p", "20", "--args", "--no-group"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "20 gcc \\\n       -o \\\n       x \\\n       x.c\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_children() {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init").cmdline(&["/sbin/init"]);
+    fake.process(100).comm("make").cmdline(&["make", "-j2"]);
+    fake.process(101)
+        .comm("cc1")
+        .ppid(100)
+        .state('R')
+        .cmdline(&["cc1", "x.c"]);
+    fake.process(102).comm("ld").ppid(101).cmdline(&["ld"]);
+    fake.process(103)
+        .comm("java")
+        .ppid(100)
+        .cmdline(&["java", "-jar", "x.jar"])
+        .thread(104, "GC Thread#0");
+
+    let out = fake.run(&["children", "-p", "100", "--threads"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "\npid 101 [R]:\ncc1 \\\n    x.c\n\
+         \npid 102 [S]:\nld\n\
+         \npid 103 [S]:\njava \\\n    -jar x.jar\n\
+         thread 104 [S]: GC Thread#0\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_children_no_such_process() {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init");
+
+    let out = fake.run(&["children", "-p", "100"]);
+
+    assert_eq!(Some(3), out.status.code());
+}
+
+fn selector_tree() -> FakeProc {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init").cmdline(&["/sbin/init"]);
+    fake.process(100)
+        .comm("postgres")
+        .uid(70)
+        .exe("/usr/bin/postgres")
+        .cmdline(&["/usr/bin/postgres", "-D", "/var/lib/pgsql"]);
+    fake.process(101)
+        .comm("postgres")
+        .ppid(100)
+        .uid(70)
+        .exe("/usr/bin/postgres")
+        .cmdline(&["postgres: checkpointer"]);
+    fake.process(200)
+        .comm("python3")
+        .uid(1000)
+        .exe("/usr/bin/python3.11")
+        .cmdline(&["python3", "manage.py", "runserver"]);
+    fake
+}
+
+#[test]
+fn test_args_select_by_cmdline() {
+    let fake = selector_tree();
+
+    let out = fake.run(&["args", "--cmdline-regex", "manage\\.py +run"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "python3 \\\n    manage.py \\\n    runserver\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+    assert!(out.stderr.is_empty());
+}
+
+#[test]
+fn test_args_select_by_cmdline_long() {
+    let classpath: Vec<String> = (0..10000)
+        .map(|n| format!("/opt/app/lib/dependency-{}.jar", n))
+        .collect();
+    let classpath = classpath.join(":");
+
+    let fake = FakeProc::new();
+    fake.process(42)
+        .cmdline(&["java", "-cp", &classpath, "com.example.Main"]);
+
+    let out = fake.run(&[
+        "args",
+        "--cmdline-regex",
+        "java.*Main",
+        "--format",
+        "ndjson",
+    ]);
+
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("\"com.example.Main\""));
+}
+
+#[test]
+fn test_args_select_ambiguous() {
+    let fake = selector_tree();
+
+    let out = fake.run(&["args", "--name", "postgres"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "mups: 2 processes match: 100 101\n",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        "\npid 100 [S]:\n/usr/bin/postgres \\\n    -D \\\n    /var/lib/pgsql\n\
+         \npid 101 [S]:\n'postgres: checkpointer'\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_select_combined() {
+    let fake = selector_tree();
+
+    let out = fake.run(&["args", "--exe", "postgres", "--parent", "100"]);
+    assert_eq!(
+        "'postgres: checkpointer'\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["args", "--user", "70", "--oldest"]);
+    assert_eq!(
+        "/usr/bin/postgres \\\n    -D \\\n    /var/lib/pgsql\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["args", "--exe", "/usr/bin/python3.11", "--newest"]);
+    assert_eq!(
+        "python3 \\\n    manage.py \\\n    runserver\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_select_no_match() {
+    let fake = selector_tree();
+
+    let out = fake.run(&["args", "--name", "nginx"]);
+
+    assert_eq!(Some(3), out.status.code());
+    assert_eq!(
+        "mups: no matching processes\n",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[test]
+fn test_whatps_multiple_pids() {
+    let fake = selector_tree();
+
+    let out = fake.run(&["whatps", "-p", "101", "-p", "200"]);
+
+    assert!(out.status.success());
+    assert!(out.stderr.is_empty());
+    assert_eq!(
+        "\npid 1 [S]:\n/sbin/init\n\
+         \npid 100 [S]:\n/usr/bin/postgres \\\n    -D \\\n    /var/lib/pgsql\n\
+         \npid 101 [S]:\n'postgres: checkpointer'\n\
+         \npid 1 [S]:\n/sbin/init\n\
+         \npid 200 [S]:\npython3 \\\n    manage.py \\\n    runserver\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_args_ndjson() {
+    let fake = selector_tree();
+    fake.process(300)
+        .comm("cat")
+        .file("cmdline", b"cat\0caf\xe9.txt\0");
+
+    let out = fake.run(&["args", "-p", "200", "-p", "300", "--format", "ndjson"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "{\"pid\":200,\"ppid\":1,\"state\":\"S\",\"comm\":\"python3\",\
+         \"argv\":[\"python3\",\"manage.py\",\"runserver\"]}\n\
+         {\"pid\":300,\"ppid\":1,\"state\":\"S\",\"comm\":\"cat\",\
+         \"argv\":[\"cat\",\"caf\u{fffd}.txt\"],\"argv_base64\":[\"Y2F0\",\"Y2Fm6S50eHQ=\"]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_whatps_json() {
+    let fake = selector_tree();
+
+    let out = fake.run(&["whatps", "-p", "101", "--format", "json"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        r#"[
+  {
+    "pid": 101,
+    "ppid": 100,
+    "state": "S",
+    "comm": "postgres",
+    "argv": [
+      "postgres: checkpointer"
+    ],
+    "ancestors": [
+      {
+        "pid": 1,
+        "ppid": 0,
+        "state": "S",
+        "comm": "init",
+        "argv": [
+          "/sbin/init"
+        ]
+      },
+      {
+        "pid": 100,
+        "ppid": 1,
+        "state": "S",
+        "comm": "postgres",
+        "argv": [
+          "/usr/bin/postgres",
+          "-D",
+          "/var/lib/pgsql"
+        ]
+      }
+    ]
+  }
+]
+"#,
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_tree_ndjson() {
+    let fake = nginx_tree();
+
+    let out = fake.run(&["tree", "-p", "110", "--format", "ndjson"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "{\"pid\":110,\"ppid\":1,\"state\":\"S\",\"comm\":\"sh\",\"argv\":[\"sh\"],\"children\":[\
+         {\"pid\":111,\"ppid\":110,\"state\":\"S\",\"comm\":\"sleep\",\"argv\":[\"sleep\",\"60\"],\
+         \"children\":[]}]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_tree_ppid_loop() {
+    let fake = FakeProc::new();
+    fake.process(5).comm("a").ppid(6).cmdline(&["a"]);
+    fake.process(6).comm("b").ppid(5).cmdline(&["b"]);
+
+    let out = fake.run(&["tree", "-p", "5", "--collapse"]);
+
+    assert!(out.status.success());
+    assert_eq!("5 a\n└─ 6 b\n", String::from_utf8_lossy(&out.stdout));
+
+    let out = fake.run(&["tree", "-p", "5", "--format", "ndjson"]);
+
+    assert!(out.status.success());
+    assert_eq!(
+        "{\"pid\":5,\"ppid\":6,\"state\":\"S\",\"comm\":\"a\",\"argv\":[\"a\"],\"children\":[\
+         {\"pid\":6,\"ppid\":5,\"state\":\"S\",\"comm\":\"b\",\"argv\":[\"b\"],\
+         \"children\":[]}]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_deleted() {
+    let fake = FakeProc::new();
+    let logs = fake.root().join("logs");
+    fs::create_dir_all(&logs).unwrap();
+    let big = logs.join("big.log (deleted)");
+    let small = logs.join("small.log (deleted)");
+    fs::write(&big, vec![0; 3000]).unwrap();
+    fs::write(&small, vec![0; 100]).unwrap();
+    let dev = mups::device_name(fs::metadata(&logs).unwrap().dev());
+
+    let app = fake
+        .process(42)
+        .comm("app")
+        .fd(3, big.to_str().unwrap())
+        .fd(4, big.to_str().unwrap())
+        .fd(5, "/var/log/gone.log (deleted)");
+    app.file(
+        "mountinfo",
+        format!("22 1 {} / /logs rw - ext4 /dev/x rw\n", dev).as_bytes(),
+    );
+    fake.process(43)
+        .comm("tail")
+        .fd(3, small.to_str().unwrap())
+        .fd(4, logs.to_str().unwrap());
+
+    let out = fake.run(&["deleted"]);
+    assert_eq!(
+        format!(
+            "  SIZE FILES FILESYSTEM\n\
+             \x20 3.0K     2 /logs ({0})\n\
+             \n  SIZE FILES     PID COMM\n\
+             \x20 2.9K     1      42 app\n\
+             \x20  100     1      43 tail\n\
+             \n  SIZE     PID   FD PATH\n\
+             \x20 2.9K      42    3 {1}/big.log\n\
+             \x20 2.9K      42    4 {1}/big.log\n\
+             \x20  100      43    3 {1}/small.log\n",
+            dev,
+            logs.display()
+        ),
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["deleted", "--format", "ndjson"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with(&format!(
+        r#"{{"filesystems":[{{"device":"{}","mount":"/logs","size":3100,"files":2}}],"processes":[{{"pid":42,"comm":"app","size":3000,"files":[{{"fd":3,"#,
+        dev
+    )));
+    assert!(stdout.contains(r#""mount":"/logs"}"#));
+    assert_eq!(1, stdout.lines().count());
+}
+
+#[test]
+fn test_env() {
+    let fake = FakeProc::new();
+    fake.process(42).cmdline(&["sleep", "10"]).environ(&[
+        "PATH=/bin",
+        "LC_ALL=C",
+        "HOME=/root",
+        "LANG=fi_FI.UTF-8",
+    ]);
+
+    let out = fake.run(&["env", "-p", "42"]);
+    assert_eq!(
+        "PATH=/bin\nLC_ALL=C\nHOME=/root\nLANG=fi_FI.UTF-8\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["env", "-p", "42", "--sort", "--var", "L*"]);
+    assert_eq!(
+        "LANG=fi_FI.UTF-8\nLC_ALL=C\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["env", "-p", "42", "--var", "HOME", "--command"]);
+    assert_eq!(
+        "env -i \\\n    HOME=/root \\\n    sleep \\\n    10\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["env", "-p", "42", "--var", "P*", "--format", "ndjson"]);
+    assert_eq!(
+        "{\"pid\":42,\"environ\":[\"PATH=/bin\"]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_env_diff() {
+    let fake = FakeProc::new();
+    fake.process(42)
+        .environ(&["PATH=/usr/bin:/bin", "LANG=C", "DEBUG=1"]);
+    fake.process(43)
+        .environ(&["PATH=/usr/local/bin:/usr/bin:/bin", "LANG=C.UTF-8"]);
+
+    let out = fake.run(&["env", "--diff", "42", "43"]);
+    assert_eq!(
+        "-DEBUG=1\n-LANG=C\n+LANG=C.UTF-8\n~PATH\n  +/usr/local/bin\n   /usr/bin\n   /bin\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&[
+        "env", "--diff", "42", "43", "--var", "D*", "--format", "ndjson",
+    ]);
+    assert_eq!(
+        "{\"pid_a\":42,\"pid_b\":43,\"changes\":[{\"name\":\"DEBUG\",\"old\":\"1\",\"new\":null}]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["env", "--diff", "42", "44"]);
+    assert_eq!(Some(3), out.status.code());
+}
+
+#[test]
+fn test_env_diff_json() {
+    let fake = FakeProc::new();
+    fake.process(42).file("environ", b"NAME=caf\xe9\0");
+    fake.process(43).environ(&["NAME=cafe"]);
+
+    let out = fake.run(&["env", "--diff", "42", "43", "--format", "ndjson"]);
+    assert_eq!(
+        "{\"pid_a\":42,\"pid_b\":43,\"changes\":[{\"name\":\"NAME\",\
+         \"old\":\"caf\u{fffd}\",\"old_base64\":\"Y2Fm6Q==\",\"new\":\"cafe\"}]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_fds() {
+    let fake = FakeProc::new();
+    let writer = fake
+        .process(42)
+        .fd(0, "/dev/null")
+        .fd(1, "pipe:[100]")
+        .fd(7, "anon_inode:[eventfd]");
+    writer.file("fdinfo/0", b"pos:\t0\nflags:\t0100000\n");
+    writer.file("fdinfo/1", b"pos:\t0\nflags:\t01\n");
+    writer.file("fdinfo/7", b"pos:\t0\nflags:\t02004002\n");
+    let reader = fake.process(43).comm("cat").fd(0, "pipe:[100]");
+    reader.file("fdinfo/0", b"pos:\t0\nflags:\t0\n");
+
+    let out = fake.run(&["fds", "-p", "42"]);
+    assert_eq!(
+        "  FD MODE        POS FLAGS            TARGET\n\
+         \x20  0 r             0 LARGEFILE        /dev/null\n\
+         \x20  1 w             0                  pipe:[100] <-> 43 (cat) fd 0 r\n\
+         \x20  7 rw            0 NONBLOCK,CLOEXEC anon_inode:[eventfd]\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["fds", "-p", "43", "--format", "ndjson"]);
+    assert_eq!(
+        "{\"fd\":0,\"type\":\"pipe\",\"target\":\"pipe:[100]\",\"mode\":\"r\",\"flags\":[],\"pos\":0,\
+         \"peers\":[{\"pid\":42,\"comm\":\"proc\",\"fd\":1,\"mode\":\"w\"}]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_fds_sockets() {
+    let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+               \x20  0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 500 1\n\
+               \x20  1: 0100007F:983C 0100007F:1F90 01 00000000:00000000 00:00000000 00000000  1000        0 501 1\n\
+               \x20  2: 0100007F:1F90 0100007F:983C 01 00000000:00000000 00:00000000 00000000  1000        0 502 1\n";
+    let unix = "Num       RefCount Protocol Flags    Type St Inode Path\n\
+                0000000000000000: 00000002 00000000 00010000 0001 01   600 /run/app.sock\n";
+
+    let fake = FakeProc::new();
+    let server = fake
+        .process(42)
+        .comm("server")
+        .fd(3, "socket:[500]")
+        .fd(4, "socket:[502]")
+        .fd(5, "socket:[600]");
+    server.file("net/tcp", tcp.as_bytes());
+    server.file("net/unix", unix.as_bytes());
+    let client = fake.process(43).comm("curl").fd(3, "socket:[501]");
+    client.file("fdinfo/3", b"pos:\t0\nflags:\t02\n");
+
+    let out = fake.run(&["fds", "-p", "42"]);
+    assert_eq!(
+        "  FD MODE        POS FLAGS TARGET\n\
+         \x20  3 ?             ? ?     socket:[500] tcp 127.0.0.1:8080 LISTEN\n\
+         \x20  4 ?             ? ?     socket:[502] tcp 127.0.0.1:8080 -> 127.0.0.1:38972 ESTABLISHED \
+         <-> 43 (curl) fd 3 rw\n\
+         \x20  5 ?             ? ?     socket:[600] unix stream /run/app.sock LISTEN\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["fds", "-p", "42", "--format", "ndjson"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains(
+        "\"socket\":{\"protocol\":\"unix\",\"type\":\"stream\",\
+         \"path\":\"/run/app.sock\",\"state\":\"LISTEN\"}"
+    ));
+}
+
+#[test]
+fn test_maps() {
+    let fake = FakeProc::new();
+    let process = fake.process(42);
+    process.file(
+        "maps",
+        b"55d5c4a00000-55d5c4a08000 r-xp 00000000 fd:01 100 /usr/bin/app\n\
+          55d5c4e00000-55d5c4e21000 rw-p 00000000 00:00 0 [heap]\n\
+          7f1c2a200000-7f1c2a400000 r-xp 00000000 fd:01 200 /usr/lib/libc.so.6\n\
+          7f1c2a400000-7f1c2a401000 rw-p 00200000 fd:01 200 /usr/lib/libc.so.6\n\
+          7ffd1c000000-7ffd1c021000 rw-p 00000000 00:00 0 [stack]\n",
+    );
+    process.file(
+        "smaps",
+        b"7f1c2a200000-7f1c2a400000 r-xp 00000000 fd:01 200 /usr/lib/libc.so.6\n\
+          Rss: 1024 kB\nPss: 512 kB\nSwap: 0 kB\n\
+          7ffd1c000000-7ffd1c021000 rw-p 00000000 00:00 0 [stack]\n\
+          Rss: 16 kB\nPss: 16 kB\nSwap: 4 kB\n",
+    );
+
+    let out = fake.run(&["maps", "-p", "42"]);
+    assert_eq!(
+        "  SIZE MAPPING\n\
+         \x20  32K /usr/bin/app\n\
+         \x20 132K [heap]\n\
+         \x20 2.0M /usr/lib/libc.so.6\n\
+         \x20 132K [stack]\n\
+         \x20 2.3M total\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["maps", "-p", "42", "--smaps"]);
+    assert_eq!(
+        "  SIZE    RSS    PSS   SWAP MAPPING\n\
+         \x20 2.0M   1.0M   512K      0 /usr/lib/libc.so.6\n\
+         \x20 132K    16K    16K   4.0K [stack]\n\
+         \x20 2.1M   1.0M   528K   4.0K total\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["maps", "-p", "42", "--regions", "--format", "ndjson"]);
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with(
+        "{\"start\":\"55d5c4a00000\",\"end\":\"55d5c4a08000\",\"perms\":\"r-xp\",\"offset\":0,\
+         \"dev\":\"fd:01\",\"inode\":100,\"kind\":\"file\",\"path\":\"/usr/bin/app\",\"size\":32768}\n"
+    ));
+
+    process.file(
+        "smaps_rollup",
+        b"55d5c4a00000-7ffd1c021000 ---p 00000000 00:00 0                          [rollup]\n\
+          Rss: 1040 kB\nPss: 528 kB\nPss_Anon: 16 kB\nSwap: 4 kB\n",
+    );
+
+    let out = fake.run(&["maps", "-p", "42", "--rollup"]);
+    assert_eq!(
+        "   RSS    PSS   SWAP\n  1.0M   528K   4.0K\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["maps", "-p", "42", "--rollup", "--format", "ndjson"]);
+    assert_eq!(
+        "{\"rss\":1064960,\"pss\":540672,\"swap\":4096}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_port() {
+    let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+               \x20  0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 500 1\n";
+    let unix = "Num       RefCount Protocol Flags    Type St Inode Path\n\
+                0000000000000000: 00000002 00000000 00010000 0001 01   600 /run/app.sock\n";
+
+    let fake = FakeProc::new();
+    fake.process(1).comm("init").cmdline(&["/sbin/init"]);
+    let master = fake
+        .process(100)
+        .comm("nginx")
+        .ppid(1)
+        .cmdline(&["nginx"])
+        .fd(6, "socket:[500]")
+        .fd(7, "socket:[600]");
+    master.file("net/tcp", tcp.as_bytes());
+    master.file("net/unix", unix.as_bytes());
+    let worker = fake
+        .process(101)
+        .comm("nginx")
+        .ppid(100)
+        .cmdline(&["nginx: worker process"])
+        .fd(6, "socket:[500]");
+    worker.file("net/tcp", tcp.as_bytes());
+
+    let out = fake.run(&["port", "8080"]);
+    assert_eq!(
+        "pid 100 fd 6: tcp 0.0.0.0:8080 LISTEN\n\
+         pid 101 fd 6: tcp 0.0.0.0:8080 LISTEN\n\
+         \npid 1 [S]:\n/sbin/init\n\
+         \npid 100 [S]:\nnginx\n\
+         \npid 1 [S]:\n/sbin/init\n\
+         \npid 100 [S]:\nnginx\n\
+         \npid 101 [S]:\n'nginx: worker process'\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["port", "--unix", "/run/app.sock", "--format", "ndjson"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains(r#""sockets":[{"fd":7,"socket":{"protocol":"unix","#));
+    assert!(stdout.contains(r#""ancestors":[{"pid":1,"#));
+
+    let out = fake.run(&["port", "--udp", "8080"]);
+    assert_eq!(Some(3), out.status.code());
+}
+
+#[test]
+fn test_prettify() {
+    let out = run_with_stdin(&["prettify"], b"ls -l 'my files'\n");
+
+    assert!(out.status.success());
+    assert_eq!(
+        "ls \\\n    -l \\\n    'my files'\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_prettify_gxx() {
+    let out = run_with_stdin(&["prettify"], b"g++ -O2 -DZ -c x.cc -DA -o x.o\n");
+
+    assert!(out.status.success());
+    assert_eq!(
+        "g++ \\\n    -O2 \\\n    -DA \\\n    -DZ \\\n    -c \\\n    x.cc \\\n    -o x.o\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_prettify_diff() {
+    let out = run_with_stdin(
+        &["prettify", "--diff"],
+        b"ls -l /tmp\nls \\\n    -la /tmp\n",
+    );
+    assert_eq!(
+        " ls \\\n-    -l \\\n+    -la \\\n     /tmp\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = run_with_stdin(&["prettify", "--diff"], b"ls -l /tmp\n");
+    assert_eq!(Some(2), out.status.code());
+}
+
+#[test]
+fn test_prettify_broken_pipe() {
+    let out = run_with_closed_stdout(&["prettify"], b"ls -l /tmp\n");
+
+    assert_eq!(Some(6), out.status.code());
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("mups: Broken pipe"));
+}
+
+#[test]
+fn test_prettify_unterminated() {
+    let out = run_with_stdin(&["prettify"], b"echo \"hello\n");
+
+    assert_eq!(Some(2), out.status.code());
+    assert_eq!(
+        "mups: unterminated double quote\n",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[test]
+fn test_who_has() {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init").cmdline(&["/sbin/init"]);
+    let db = fake
+        .process(42)
+        .comm("db")
+        .ppid(1)
+        .cmdline(&["db", "--data", "/mnt/vol1/db"])
+        .cwd("/mnt/vol1/db")
+        .fd(3, "/mnt/vol1/db/wal.log (deleted)")
+        .fd(4, "/mnt/vol10/other");
+    db.file("fdinfo/3", b"pos:\t0\nflags:\t02\n");
+    db.file(
+        "maps",
+        b"7f1c2a400000-7f1c2a428000 r--p 00000000 fd:01 12 /mnt/vol1/lib/libx.so\n\
+          7f1c2a428000-7f1c2a430000 r-xp 00028000 fd:01 12 /mnt/vol1/lib/libx.so\n\
+          7ffd1c000000-7ffd1c021000 rw-p 00000000 00:00 0  [stack]\n",
+    );
+    fake.process(43).comm("sh").ppid(1).cwd("/home");
+
+    let out = fake.run(&["who-has", "/mnt/vol1"]);
+    assert_eq!(
+        "pid 42 cwd: /mnt/vol1/db\n\
+         pid 42 fd 3 rw: /mnt/vol1/db/wal.log (deleted)\n\
+         pid 42 mapped: /mnt/vol1/lib/libx.so\n\
+         \npid 1 [S]:\n/sbin/init\n\
+         \npid 42 [S]:\ndb \\\n    --data /mnt/vol1/db\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = fake.run(&["who-has", "/mnt/vol1/db/wal.log", "--format", "ndjson"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains(
+        r#""access":[{"kind":"fd","fd":3,"mode":"rw","path":"/mnt/vol1/db/wal.log","deleted":true}]"#
+    ));
+
+    let out = fake.run(&["who-has", "/mnt/vol2"]);
+    assert_eq!(Some(3), out.status.code());
+}
@@ -0,0 +1,258 @@
+//! Fake procfs trees for tests
+//!
+//! A `FakeProc` is a temporary directory laid out like /proc, which
+//! can be handed to `ProcFs::new` in place of the real thing. The
+//! files are written out as soon as the corresponding setter of a
+//! `FakeProcess` is called, so a test can also change or remove them
+//! half way to imitate processes that change or exit while mups is
+//! looking at them.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use mups::ProcFs;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub struct FakeProc {
+    root: PathBuf,
+}
+
+impl FakeProc {
+    pub fn new() -> FakeProc {
+        let root = std::env::temp_dir().join(format!(
+            "mups-test-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        fs::create_dir_all(&root).unwrap();
+
+        FakeProc { root }
+    }
+
+    /// Add a process, initially a sleeping child of init
+    pub fn process(&self, pid: u32) -> FakeProcess {
+        let process = FakeProcess {
+            dir: self.root.join(pid.to_string()),
+            pid,
+            comm: String::from("proc"),
+            state: 'S',
+            ppid: if pid == 1 { 0 } else { 1 },
+            uid: 0,
+        };
+
+        fs::create_dir_all(process.dir.join("fd")).unwrap();
+        process.write_stat();
+        process.write_status();
+        process.file("cmdline", b"");
+        process.file("environ", b"");
+
+        process
+    }
+
+    pub fn procfs(&self) -> ProcFs {
+        ProcFs::new(&self.root)
+    }
+
+    /// Make the process go away, as if it had exited
+    pub fn remove(&self, pid: u32) {
+        fs::remove_dir_all(self.root.join(pid.to_string())).unwrap();
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Run the mups binary against this tree
+    pub fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_mups"))
+            .arg("--proc-root")
+            .arg(&self.root)
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+/// Run the mups binary with the given input, without any fake tree
+pub fn run_with_stdin(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mups"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Like `run_with_stdin`, but with nobody reading the output
+pub fn run_with_closed_stdout(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mups"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    drop(child.stdout.take());
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+impl Drop for FakeProc {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+pub struct FakeProcess {
+    dir: PathBuf,
+    pid: u32,
+    comm: String,
+    state: char,
+    ppid: u32,
+    uid: u32,
+}
+
+impl FakeProcess {
+    pub fn cmdline(self, args: &[&str]) -> FakeProcess {
+        let mut buf = Vec::new();
+
+        for arg in args {
+            buf.extend_from_slice(arg.as_bytes());
+            buf.push(0);
+        }
+
+        self.file("cmdline", &buf);
+        self
+    }
+
+    pub fn comm(mut self, comm: &str) -> FakeProcess {
+        self.comm = String::from(comm);
+        self.write_stat();
+        self.write_status();
+        self
+    }
+
+    pub fn cwd(self, target: &str) -> FakeProcess {
+        self.link("cwd", target);
+        self
+    }
+
+    pub fn environ(self, vars: &[&str]) -> FakeProcess {
+        let mut buf = Vec::new();
+
+        for var in vars {
+            buf.extend_from_slice(var.as_bytes());
+            buf.push(0);
+        }
+
+        self.file("environ", &buf);
+        self
+    }
+
+    pub fn exe(self, target: &str) -> FakeProcess {
+        self.link("exe", target);
+        self
+    }
+
+    pub fn fd(self, fd: u32, target: &str) -> FakeProcess {
+        self.link(&format!("fd/{}", fd), target);
+        self
+    }
+
+    /// Write any file of the process with the given contents
+    pub fn file(&self, name: &str, contents: &[u8]) {
+        let path = self.dir.join(name);
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    pub fn ppid(mut self, ppid: u32) -> FakeProcess {
+        self.ppid = ppid;
+        self.write_stat();
+        self.write_status();
+        self
+    }
+
+    pub fn state(mut self, state: char) -> FakeProcess {
+        self.state = state;
+        self.write_stat();
+        self.write_status();
+        self
+    }
+
+    /// Add a thread other than the main thread
+    pub fn thread(self, tid: u32, comm: &str) -> FakeProcess {
+        let stat = stat_line(tid, comm, 'S', self.ppid);
+
+        self.file(&format!("task/{}/stat", tid), stat.as_bytes());
+        self
+    }
+
+    pub fn uid(mut self, uid: u32) -> FakeProcess {
+        self.uid = uid;
+        self.write_status();
+        self
+    }
+
+    fn link(&self, name: &str, target: &str) {
+        let path = self.dir.join(name);
+
+        let _ = fs::remove_file(&path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        symlink(target, path).unwrap();
+    }
+
+    fn write_stat(&self) {
+        let stat = stat_line(self.pid, &self.comm, self.state, self.ppid);
+
+        self.file("stat", stat.as_bytes());
+        self.file(&format!("task/{}/stat", self.pid), stat.as_bytes());
+    }
+
+    fn write_status(&self) {
+        let status = format!(
+            "Name:\t{}\nUmask:\t0022\nState:\t{}\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\n\
+             Uid:\t{uid}\t{uid}\t{uid}\t{uid}\nGid:\t{uid}\t{uid}\t{uid}\t{uid}\nThreads:\t1\n",
+            self.comm,
+            self.state,
+            self.pid,
+            self.pid,
+            self.ppid,
+            uid = self.uid
+        );
+
+        self.file("status", status.as_bytes());
+    }
+}
+
+fn stat_line(pid: u32, comm: &str, state: char, ppid: u32) -> String {
+    format!(
+        "{} ({}) {} {} {} {} 0 -1 4194560 120 0 3 0 5 2 0 0 20 0 1 0 {} \
+         8192000 250 18446744073709551615 1 1 0 0 0 0 0 4096 1088 0 0 0 17 0 \
+         0 0 0 0 0 0 0 0 0 0 0 0 0\n",
+        pid,
+        comm,
+        state,
+        ppid,
+        pid,
+        pid,
+        1000 + pid
+    )
+}
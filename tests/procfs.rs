@@ -0,0 +1,84 @@
+extern crate mups;
+
+mod common;
+
+use common::FakeProc;
+use mups::{ArgFormat, MupsError, ProcStat};
+
+#[test]
+fn test_read_pid_nasty_comm() {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init");
+    fake.process(42).comm("a) (b c").ppid(1).state('R');
+
+    let stat = ProcStat::read_pid(&fake.procfs(), 42).unwrap();
+
+    assert_eq!(42, stat.pid);
+    assert_eq!("a) (b c", &stat.comm);
+    assert_eq!('R', stat.state);
+    assert_eq!(1, stat.ppid);
+}
+
+#[test]
+fn test_read_pid_malformed() {
+    let fake = FakeProc::new();
+    fake.process(42).file("stat", b"42 (foo) S\n");
+
+    match ProcStat::read_pid(&fake.procfs(), 42) {
+        Err(MupsError::MalformedProcFile { content, .. }) => assert_eq!("42 (foo) S\n", content),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn test_ancestors() {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init");
+    fake.process(100).comm("sh").ppid(1);
+    fake.process(200).comm("make").ppid(100);
+
+    assert_eq!(vec![200, 100, 1], fake.procfs().ancestors(200).unwrap());
+    assert_eq!(vec![1], fake.procfs().ancestors(1).unwrap());
+}
+
+#[test]
+fn test_ancestors_vanishing_parent() {
+    let fake = FakeProc::new();
+    fake.process(1).comm("init");
+    fake.process(100).comm("sh").ppid(1);
+    fake.process(200).comm("make").ppid(100);
+    fake.remove(100);
+
+    assert_eq!(vec![200, 100], fake.procfs().ancestors(200).unwrap());
+
+    match fake.procfs().ancestors(100) {
+        Err(MupsError::NoSuchProcess(100)) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn test_kernel_thread_cmdline() {
+    let fake = FakeProc::new();
+    fake.process(2).comm("kthreadd").ppid(0);
+
+    let procfs = fake.procfs();
+    assert!(procfs.read_cmdline(2).unwrap().is_empty());
+
+    let mut out = Vec::new();
+    procfs
+        .write_cmdline(&mut out, 2, &ArgFormat::default())
+        .unwrap();
+    assert_eq!(b"\n", &out[..]);
+}
+
+#[test]
+fn test_cmdline_without_terminator() {
+    let fake = FakeProc::new();
+    fake.process(42).file("cmdline", b"nginx: worker process");
+
+    assert_eq!(
+        vec![b"nginx: worker process".to_vec()],
+        fake.procfs().read_cmdline(42).unwrap()
+    );
+}